@@ -0,0 +1,330 @@
+use crate::chunk::*;
+use crate::compiler::*;
+use crate::diagnostic::*;
+use crate::memory::*;
+use crate::object::*;
+use crate::value::*;
+use crate::verify::*;
+use crate::vm::*;
+use std::fmt;
+
+pub const BYTECODE_MAGIC: &[u8; 4] = b"LOXC";
+pub const BYTECODE_VERSION: u16 = 3;
+
+const CONSTANT_NUMBER: u8 = 0;
+const CONSTANT_STRING: u8 = 1;
+const CONSTANT_FUNCTION: u8 = 2;
+
+const NO_NAME: u32 = u32::MAX;
+
+/// Why a bytecode file could not be loaded: either it does not parse as the
+/// `.loxc` format, or it parses but fails verification.
+#[derive(Debug, Clone)]
+pub enum LoadError {
+    LoadFormat(String),
+    LoadVerify(VerifyError),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::LoadFormat(message) => write!(f, "{}", message),
+            LoadError::LoadVerify(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+// Nested function constants are read recursively, so bound how deep a
+// crafted file can make that recursion go.
+const MAX_FUNCTION_DEPTH: usize = 1024;
+
+/// Compiles `source` and serializes the resulting script, or returns the
+/// diagnostics if it does not compile.
+pub fn compile_bytecode(
+    source: String,
+    vm: &mut VirtualMachine,
+) -> Result<Vec<u8>, Vec<Diagnostic>> {
+    let result = compile(source, vm);
+
+    if !result.success {
+        return Err(result.diagnostics);
+    }
+
+    return Ok(write_bytecode(result.function));
+}
+
+pub(crate) fn write_bytecode(function: *mut Obj) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(BYTECODE_MAGIC);
+    bytes.extend_from_slice(&BYTECODE_VERSION.to_le_bytes());
+
+    write_function(&mut bytes, object_function(function));
+
+    return bytes;
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend_from_slice(&(value as u32).to_le_bytes());
+}
+
+fn write_string(bytes: &mut Vec<u8>, string: &ObjString) {
+    write_u32(bytes, string.chars.len());
+
+    bytes.extend_from_slice(string.chars.as_bytes());
+}
+
+fn write_function(bytes: &mut Vec<u8>, function: &ObjFunction) {
+    if function.name.is_null() {
+        bytes.extend_from_slice(&NO_NAME.to_le_bytes());
+    } else {
+        write_string(bytes, object_string(function.name));
+    }
+
+    write_u32(bytes, function.arity);
+    write_u32(bytes, function.upvalue_count);
+
+    let chunk = &function.chunk;
+
+    write_u32(bytes, chunk.code.len());
+
+    bytes.extend_from_slice(&chunk.code);
+
+    write_u32(bytes, chunk.lines.len());
+
+    for start in chunk.lines.iter() {
+        write_u32(bytes, start.offset as usize);
+
+        bytes.extend_from_slice(&start.line.to_le_bytes());
+    }
+
+    write_u32(bytes, chunk.columns.len());
+
+    bytes.extend_from_slice(&chunk.columns);
+
+    write_u32(bytes, chunk.constants.values.len());
+
+    for constant in chunk.constants.values.iter() {
+        match *constant {
+            Value::ValNumber(number) => {
+                bytes.push(CONSTANT_NUMBER);
+                bytes.extend_from_slice(&number.to_le_bytes());
+            }
+            Value::ValObj(object) if is_string(*constant) => {
+                bytes.push(CONSTANT_STRING);
+
+                write_string(bytes, object_string(object));
+            }
+            Value::ValObj(object) => {
+                bytes.push(CONSTANT_FUNCTION);
+
+                write_function(bytes, object_function(object));
+            }
+            _ => panic!("Cannot serialize constant."),
+        }
+    }
+}
+
+struct BytecodeReader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    depth: usize,
+}
+
+impl BytecodeReader<'_> {
+    fn read_bytes(&mut self, count: usize) -> Result<&[u8], String> {
+        if self.bytes.len() - self.offset < count {
+            return Err("Unexpected end of bytecode.".to_string());
+        }
+
+        self.offset += count;
+
+        return Ok(&self.bytes[self.offset - count..self.offset]);
+    }
+
+    fn read_u8(&mut self) -> Result<u8, String> {
+        return Ok(self.read_bytes(1)?[0]);
+    }
+
+    fn read_u32(&mut self) -> Result<u32, String> {
+        let mut buffer = [0; 4];
+
+        buffer.copy_from_slice(self.read_bytes(4)?);
+
+        return Ok(u32::from_le_bytes(buffer));
+    }
+
+    fn read_i32(&mut self) -> Result<i32, String> {
+        return Ok(self.read_u32()? as i32);
+    }
+
+    fn read_f64(&mut self) -> Result<f64, String> {
+        let mut buffer = [0; 8];
+
+        buffer.copy_from_slice(self.read_bytes(8)?);
+
+        return Ok(f64::from_le_bytes(buffer));
+    }
+
+    fn read_chars(&mut self, length: u32) -> Result<String, String> {
+        let bytes = self.read_bytes(length as usize)?;
+
+        return String::from_utf8(bytes.to_vec())
+            .map_err(|_| "String constant is not valid UTF-8.".to_string());
+    }
+}
+
+fn in_order(offset: usize, previous: Option<usize>, code: &[u8]) -> bool {
+    let after_previous = match previous {
+        Some(previous) => offset > previous,
+        None => offset == 0,
+    };
+
+    return offset < code.len() && after_previous;
+}
+
+pub(crate) fn read_bytecode(vm: &mut VirtualMachine, bytes: &[u8]) -> Result<*mut Obj, String> {
+    if bytes.len() < BYTECODE_MAGIC.len() + 2 || &bytes[..BYTECODE_MAGIC.len()] != BYTECODE_MAGIC {
+        return Err("Not a compiled lox file.".to_string());
+    }
+
+    let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+
+    if version != BYTECODE_VERSION {
+        return Err(format!(
+            "Unsupported bytecode version {} (expected {}).",
+            version, BYTECODE_VERSION
+        ));
+    }
+
+    let mut reader = BytecodeReader {
+        bytes,
+        offset: 6,
+        depth: 0,
+    };
+
+    let function = read_function(vm, &mut reader)?;
+
+    if reader.offset != bytes.len() {
+        return Err("Unexpected trailing bytes after bytecode.".to_string());
+    }
+
+    return Ok(function);
+}
+
+fn read_function(vm: &mut VirtualMachine, reader: &mut BytecodeReader) -> Result<*mut Obj, String> {
+    if reader.depth == MAX_FUNCTION_DEPTH {
+        return Err("Functions nested too deeply.".to_string());
+    }
+
+    let function = new_function(vm);
+
+    vm.compiler_roots.push(function);
+
+    reader.depth += 1;
+
+    let result = read_function_body(vm, reader, function);
+
+    reader.depth -= 1;
+
+    vm.compiler_roots.pop();
+
+    track_growth(vm, 0, chunk_size(&object_function(function).chunk));
+
+    return result.map(|_| function);
+}
+
+fn read_function_body(
+    vm: &mut VirtualMachine,
+    reader: &mut BytecodeReader,
+    function: *mut Obj,
+) -> Result<(), String> {
+    let name_length = reader.read_u32()?;
+
+    if name_length != NO_NAME {
+        let chars = reader.read_chars(name_length)?;
+
+        object_function(function).name = copy_string(vm, &chars);
+    }
+
+    object_function(function).arity = reader.read_u32()? as usize;
+    object_function(function).upvalue_count = reader.read_u32()? as usize;
+
+    let code_length = reader.read_u32()? as usize;
+
+    let code = reader.read_bytes(code_length)?.to_vec();
+
+    let line_count = reader.read_u32()?;
+
+    let mut lines = Vec::new();
+
+    for _ in 0..line_count {
+        let offset = reader.read_u32()?;
+        let line = reader.read_i32()?;
+
+        let previous = lines.last().map(|start: &LineStart| start.offset);
+
+        if !in_order(
+            offset as usize,
+            previous.map(|offset| offset as usize),
+            &code,
+        ) {
+            return Err("Malformed line table.".to_string());
+        }
+
+        lines.push(LineStart { offset, line });
+    }
+
+    if lines.is_empty() && !code.is_empty() {
+        return Err("Malformed line table.".to_string());
+    }
+
+    let columns_length = reader.read_u32()? as usize;
+
+    let columns = reader.read_bytes(columns_length)?.to_vec();
+
+    let chunk = &mut object_function(function).chunk;
+
+    chunk.code = code;
+    chunk.lines = lines;
+    chunk.columns = columns;
+
+    let starts = chunk
+        .column_starts()
+        .ok_or_else(|| "Malformed column table.".to_string())?;
+
+    let mut previous = None;
+
+    for start in starts.iter() {
+        if !in_order(start.offset, previous, &chunk.code) {
+            return Err("Malformed column table.".to_string());
+        }
+
+        previous = Some(start.offset);
+    }
+
+    if starts.is_empty() && !chunk.code.is_empty() {
+        return Err("Malformed column table.".to_string());
+    }
+
+    let constant_count = reader.read_u32()?;
+
+    for _ in 0..constant_count {
+        let constant = match reader.read_u8()? {
+            CONSTANT_NUMBER => Value::ValNumber(reader.read_f64()?),
+            CONSTANT_STRING => {
+                let length = reader.read_u32()?;
+
+                let chars = reader.read_chars(length)?;
+
+                Value::ValObj(copy_string(vm, &chars))
+            }
+            CONSTANT_FUNCTION => Value::ValObj(read_function(vm, reader)?),
+            tag => return Err(format!("Unknown constant tag {}.", tag)),
+        };
+
+        write_value_array(&mut object_function(function).chunk.constants, constant);
+    }
+
+    return Ok(());
+}
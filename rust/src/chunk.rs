@@ -1,41 +1,224 @@
-use crate::common::*;
 use crate::value::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct LineStart {
+    pub offset: u32,
+    pub line: i32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct ColumnStart {
+    pub offset: usize,
+    pub col: i32,
+    pub length: i32,
+}
 
 #[derive(Clone)]
 pub struct Chunk {
     pub code: Vec<u8>,
-    pub lines: Vec<i32>,
+    pub lines: Vec<LineStart>,
+    /// Varint records of `(offset delta, col, length)`, one for every
+    /// instruction whose column or token length differs from the one before.
+    pub columns: Vec<u8>,
+    last_column: Option<ColumnStart>,
     pub constants: ValueArray,
+    constant_indices: HashMap<ValueKey, usize>,
 }
 
-pub fn copy_chunk(chunk: &Chunk) -> Chunk {
-    Chunk {
-        code: chunk.code.clone(),
-        lines: chunk.lines.clone(),
-        constants: chunk.constants.clone(),
+impl Chunk {
+    pub fn get_line(&self, offset: usize) -> i32 {
+        let index = self
+            .lines
+            .partition_point(|start| start.offset as usize <= offset);
+
+        return self.lines[index - 1].line;
+    }
+
+    pub fn get_col(&self, offset: usize) -> i32 {
+        return self.column_start(offset).col;
+    }
+
+    pub fn get_length(&self, offset: usize) -> i32 {
+        return self.column_start(offset).length;
+    }
+
+    fn column_start(&self, offset: usize) -> ColumnStart {
+        let mut found = ColumnStart {
+            offset: 0,
+            col: 0,
+            length: 0,
+        };
+
+        let mut reader = read_columns(&self.columns);
+
+        while let Some(start) = reader.next_start() {
+            if start.offset > offset {
+                break;
+            }
+
+            found = start;
+        }
+
+        return found;
     }
+
+    /// Decodes the whole column table, or `None` if it is malformed.
+    pub fn column_starts(&self) -> Option<Vec<ColumnStart>> {
+        let mut starts = Vec::new();
+
+        let mut reader = read_columns(&self.columns);
+
+        while let Some(start) = reader.next_start() {
+            starts.push(start);
+        }
+
+        if reader.malformed {
+            return None;
+        }
+
+        return Some(starts);
+    }
+}
+
+struct ColumnReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    offset: usize,
+    malformed: bool,
+}
+
+fn read_columns(bytes: &[u8]) -> ColumnReader<'_> {
+    ColumnReader {
+        bytes,
+        position: 0,
+        offset: 0,
+        malformed: false,
+    }
+}
+
+impl ColumnReader<'_> {
+    fn next_start(&mut self) -> Option<ColumnStart> {
+        if self.malformed || self.position >= self.bytes.len() {
+            return None;
+        }
+
+        let delta = read_varint(self.bytes, &mut self.position);
+        let col = read_varint(self.bytes, &mut self.position);
+        let length = read_varint(self.bytes, &mut self.position);
+
+        match (delta, col, length) {
+            (Some(delta), Some(col), Some(length)) => {
+                self.offset = self.offset.saturating_add(delta as usize);
+
+                return Some(ColumnStart {
+                    offset: self.offset,
+                    col: col.min(i32::MAX as u32) as i32,
+                    length: length.min(i32::MAX as u32) as i32,
+                });
+            }
+            _ => {
+                self.malformed = true;
+
+                return None;
+            }
+        }
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        bytes.push((value as u8 & 0x7f) | 0x80);
+
+        value >>= 7;
+    }
+
+    bytes.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> Option<u32> {
+    let mut value: u32 = 0;
+
+    for shift in (0..32).step_by(7) {
+        let byte = *bytes.get(*position)?;
+
+        *position += 1;
+
+        value |= ((byte & 0x7f) as u32).checked_shl(shift)?;
+
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    return None;
 }
 
 pub fn init_chunk() -> Chunk {
     Chunk {
         code: Vec::new(),
         lines: Vec::new(),
+        columns: Vec::new(),
+        last_column: None,
         constants: init_value_array(),
+        constant_indices: HashMap::new(),
     }
 }
 
-pub fn write_chunk_opcode(chunk: &mut Chunk, op: OpCode, line: i32) {
-    chunk.code.push(opcode_to_u8(op));
-    chunk.lines.push(line);
+/// Heap bytes owned by the chunk's code, debug tables and constant pool.
+pub fn chunk_size(chunk: &Chunk) -> usize {
+    return chunk.code.capacity()
+        + chunk.lines.capacity() * std::mem::size_of::<LineStart>()
+        + chunk.columns.capacity()
+        + chunk.constants.values.capacity() * std::mem::size_of::<Value>()
+        + chunk.constant_indices.capacity() * std::mem::size_of::<(ValueKey, usize)>();
 }
 
-pub fn write_chunk_u8(chunk: &mut Chunk, value: u8, line: i32) {
+pub fn write_chunk_u8(chunk: &mut Chunk, value: u8, line: i32, col: i32, length: i32) {
     chunk.code.push(value);
-    chunk.lines.push(line);
+
+    let offset = chunk.code.len() - 1;
+
+    if chunk.lines.last().map(|start| start.line) != Some(line) {
+        chunk.lines.push(LineStart {
+            offset: offset as u32,
+            line,
+        });
+    }
+
+    let previous = chunk.last_column;
+
+    if let Some(previous) = previous {
+        if previous.col == col && previous.length == length {
+            return;
+        }
+    }
+
+    let delta = offset - previous.map_or(0, |previous| previous.offset);
+
+    write_varint(&mut chunk.columns, delta as u32);
+    write_varint(&mut chunk.columns, col.max(0) as u32);
+    write_varint(&mut chunk.columns, length.max(0) as u32);
+
+    chunk.last_column = Some(ColumnStart {
+        offset,
+        col,
+        length,
+    });
 }
 
-pub fn add_constant(chunk: &mut Chunk, value: Value) -> u8 {
+pub fn add_constant(chunk: &mut Chunk, value: Value) -> usize {
+    let key = value_key(value);
+
+    if let Some(index) = chunk.constant_indices.get(&key) {
+        return *index;
+    }
+
     write_value_array(&mut chunk.constants, value);
 
-    return (chunk.constants.values.len() - 1) as u8;
+    let index = chunk.constants.values.len() - 1;
+
+    chunk.constant_indices.insert(key, index);
+
+    return index;
 }
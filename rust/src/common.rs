@@ -1,52 +1,186 @@
-use std::collections::HashMap;
+use crate::diagnostic::*;
+use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(Debug, PartialEq, Eq, Hash)]
+pub const UINT8_COUNT: usize = u8::MAX as usize + 1;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum OpCode {
-    OpReturn,
-    OpNegate,
-    OpAdd,
-    OpSubtract,
-    OpMultiply,
-    OpDivide,
-    OpConstant,
+    OpReturn = 0,
+    OpNegate = 1,
+    OpAdd = 2,
+    OpSubtract = 3,
+    OpMultiply = 4,
+    OpDivide = 5,
+    OpConstant = 6,
+    OpNil = 7,
+    OpTrue = 8,
+    OpFalse = 9,
+    OpNot = 10,
+    OpEqual = 11,
+    OpGreater = 12,
+    OpLess = 13,
+    OpPrint = 14,
+    OpPop = 15,
+    OpDefineGlobal = 16,
+    OpGetGlobal = 17,
+    OpSetGlobal = 18,
+    OpGetLocal = 19,
+    OpSetLocal = 20,
+    OpJump = 21,
+    OpJumpIfFalse = 22,
+    OpLoop = 23,
+    OpCall = 24,
+    OpClosure = 25,
+    OpGetUpvalue = 26,
+    OpSetUpvalue = 27,
+    OpCloseUpvalue = 28,
+    OpClass = 29,
+    OpGetProperty = 30,
+    OpSetProperty = 31,
+    OpMethod = 32,
+    OpInherit = 33,
+    OpGetSuper = 34,
+    OpConstantLong = 35,
+    OpDefineGlobalLong = 36,
+    OpGetGlobalLong = 37,
+    OpSetGlobalLong = 38,
+    OpClosureLong = 39,
+    OpClassLong = 40,
+    OpGetPropertyLong = 41,
+    OpSetPropertyLong = 42,
+    OpMethodLong = 43,
+    OpGetSuperLong = 44,
 }
 
-pub type OpCodeTable = HashMap<OpCode, u8>;
-
 pub enum InterpretResult {
     InterpretOk,
-    InterpretCompilerError,
-    InterpretRuntimeError,
+    InterpretCompilerError(Vec<Diagnostic>),
+    InterpretRuntimeError(RuntimeError),
 }
 
-pub fn op_code_table() -> OpCodeTable {
-    let mut table = OpCodeTable::new();
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub line: i32,
+    pub col: i32,
+    pub length: i32,
+    pub trace: Vec<TraceFrame>,
+}
 
-    table.insert(OpCode::OpReturn, 0);
-    table.insert(OpCode::OpNegate, 1);
-    table.insert(OpCode::OpAdd, 2);
-    table.insert(OpCode::OpSubtract, 3);
-    table.insert(OpCode::OpMultiply, 4);
-    table.insert(OpCode::OpDivide, 5);
-    table.insert(OpCode::OpConstant, 6);
+/// One call frame of a runtime error's stack trace, innermost first. `function`
+/// is `None` for the top-level script.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFrame {
+    pub function: Option<String>,
+    pub line: i32,
+}
 
-    return table;
+impl fmt::Display for TraceFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "[line {}] in {}()", self.line, name),
+            None => write!(f, "[line {}] in script", self.line),
+        }
+    }
 }
 
-pub fn u8_to_opcode(i: u8) -> Option<OpCode> {
-    let table = op_code_table();
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
 
-    for (opcode, value) in table {
-        if value == i as u8 {
-            return Some(opcode);
+        for frame in self.trace.iter() {
+            write!(f, "\n{}", frame)?;
         }
+
+        Ok(())
     }
+}
 
-    return None;
+impl TryFrom<u8> for OpCode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, Self::Error> {
+        let opcode = match byte {
+            0 => OpCode::OpReturn,
+            1 => OpCode::OpNegate,
+            2 => OpCode::OpAdd,
+            3 => OpCode::OpSubtract,
+            4 => OpCode::OpMultiply,
+            5 => OpCode::OpDivide,
+            6 => OpCode::OpConstant,
+            7 => OpCode::OpNil,
+            8 => OpCode::OpTrue,
+            9 => OpCode::OpFalse,
+            10 => OpCode::OpNot,
+            11 => OpCode::OpEqual,
+            12 => OpCode::OpGreater,
+            13 => OpCode::OpLess,
+            14 => OpCode::OpPrint,
+            15 => OpCode::OpPop,
+            16 => OpCode::OpDefineGlobal,
+            17 => OpCode::OpGetGlobal,
+            18 => OpCode::OpSetGlobal,
+            19 => OpCode::OpGetLocal,
+            20 => OpCode::OpSetLocal,
+            21 => OpCode::OpJump,
+            22 => OpCode::OpJumpIfFalse,
+            23 => OpCode::OpLoop,
+            24 => OpCode::OpCall,
+            25 => OpCode::OpClosure,
+            26 => OpCode::OpGetUpvalue,
+            27 => OpCode::OpSetUpvalue,
+            28 => OpCode::OpCloseUpvalue,
+            29 => OpCode::OpClass,
+            30 => OpCode::OpGetProperty,
+            31 => OpCode::OpSetProperty,
+            32 => OpCode::OpMethod,
+            33 => OpCode::OpInherit,
+            34 => OpCode::OpGetSuper,
+            35 => OpCode::OpConstantLong,
+            36 => OpCode::OpDefineGlobalLong,
+            37 => OpCode::OpGetGlobalLong,
+            38 => OpCode::OpSetGlobalLong,
+            39 => OpCode::OpClosureLong,
+            40 => OpCode::OpClassLong,
+            41 => OpCode::OpGetPropertyLong,
+            42 => OpCode::OpSetPropertyLong,
+            43 => OpCode::OpMethodLong,
+            44 => OpCode::OpGetSuperLong,
+            _ => return Err(byte),
+        };
+
+        return Ok(opcode);
+    }
 }
 
 pub fn opcode_to_u8(opcode: OpCode) -> u8 {
-    let table = op_code_table();
+    return opcode as u8;
+}
+
+/// Instructions with a constant index operand, paired with the form that takes
+/// a 24-bit big-endian index once the pool outgrows a single byte.
+const LONG_OPCODES: [(OpCode, OpCode); 10] = [
+    (OpCode::OpConstant, OpCode::OpConstantLong),
+    (OpCode::OpDefineGlobal, OpCode::OpDefineGlobalLong),
+    (OpCode::OpGetGlobal, OpCode::OpGetGlobalLong),
+    (OpCode::OpSetGlobal, OpCode::OpSetGlobalLong),
+    (OpCode::OpClosure, OpCode::OpClosureLong),
+    (OpCode::OpClass, OpCode::OpClassLong),
+    (OpCode::OpGetProperty, OpCode::OpGetPropertyLong),
+    (OpCode::OpSetProperty, OpCode::OpSetPropertyLong),
+    (OpCode::OpMethod, OpCode::OpMethodLong),
+    (OpCode::OpGetSuper, OpCode::OpGetSuperLong),
+];
+
+pub fn long_opcode(opcode: OpCode) -> Option<OpCode> {
+    return LONG_OPCODES
+        .iter()
+        .find(|(short, _)| *short == opcode)
+        .map(|(_, long)| *long);
+}
 
-    return table[&opcode];
+pub fn is_long_opcode(opcode: OpCode) -> bool {
+    return LONG_OPCODES.iter().any(|(_, long)| *long == opcode);
 }
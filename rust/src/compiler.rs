@@ -1,10 +1,14 @@
 use crate::chunk::*;
 use crate::common::*;
+use crate::diagnostic::*;
+use crate::memory::*;
+use crate::object::*;
 use crate::scanner::*;
 use crate::value::*;
-use std::collections::HashMap;
+use crate::vm::*;
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[repr(u8)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum Precedence {
     PrecNone,
     PrecAssignment,
@@ -19,188 +23,220 @@ pub enum Precedence {
     PrecPrimary,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ParseRuleFn {
     FnNone,
     FnNumber,
     FnUnary,
     FnBinary,
     FnGrouping,
+    FnLiteral,
+    FnString,
+    FnVariable,
+    FnAnd,
+    FnOr,
+    FnCall,
+    FnDot,
+    FnThis,
+    FnSuper,
 }
 
-static ParseRuleNone: ParseRule = ParseRule {
+const PARSE_RULE_NONE: ParseRule = ParseRule {
     prefix: ParseRuleFn::FnNone,
     infix: ParseRuleFn::FnNone,
     precendence: Precedence::PrecNone,
 };
 
-pub type PrecTable = HashMap<Precedence, u8>;
-
-pub type ParseRuleFnTable = HashMap<TokenKind, ParseRule>;
-
-pub fn prec_table() -> PrecTable {
-    let mut table = PrecTable::new();
-
-    table.insert(Precedence::PrecNone, 0);
-    table.insert(Precedence::PrecAssignment, 1);
-    table.insert(Precedence::PrecOr, 2);
-    table.insert(Precedence::PrecAnd, 3);
-    table.insert(Precedence::PrecEquality, 4);
-    table.insert(Precedence::PrecComparison, 5);
-    table.insert(Precedence::PrecTerm, 6);
-    table.insert(Precedence::PrecFactor, 7);
-    table.insert(Precedence::PrecUnary, 8);
-    table.insert(Precedence::PrecCall, 9);
-    table.insert(Precedence::PrecPrimary, 10);
-
-    return table;
-}
-
-pub fn precendence_to_order(prec: Precedence) -> u8 {
-    let table = prec_table();
-
-    return *table.get(&prec).unwrap();
-}
-
 pub fn order_to_precendence(order: u8) -> Precedence {
-    let table = prec_table();
-
-    for (prec, prec_order) in table.iter() {
-        if *prec_order == order {
-            return *prec;
-        }
+    match order {
+        0 => Precedence::PrecNone,
+        1 => Precedence::PrecAssignment,
+        2 => Precedence::PrecOr,
+        3 => Precedence::PrecAnd,
+        4 => Precedence::PrecEquality,
+        5 => Precedence::PrecComparison,
+        6 => Precedence::PrecTerm,
+        7 => Precedence::PrecFactor,
+        8 => Precedence::PrecUnary,
+        9 => Precedence::PrecCall,
+        _ => Precedence::PrecPrimary,
     }
-
-    panic!("Invalid precedence order: {}", order);
 }
 
-pub fn fn_table() -> ParseRuleFnTable {
-    let mut table = ParseRuleFnTable::new();
-
-    table.insert(
-        TokenKind::LeftParen,
-        ParseRule {
-            prefix: ParseRuleFn::FnGrouping,
-            infix: ParseRuleFn::FnNone,
-            precendence: Precedence::PrecNone,
-        },
-    );
-
-    table.insert(TokenKind::RightParen, ParseRuleNone.clone());
-
-    table.insert(TokenKind::LeftBrace, ParseRuleNone.clone());
-
-    table.insert(TokenKind::RightBrace, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Comma, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Dot, ParseRuleNone.clone());
-
-    table.insert(
-        TokenKind::Minus,
-        ParseRule {
-            prefix: ParseRuleFn::FnUnary,
-            infix: ParseRuleFn::FnBinary,
-            precendence: Precedence::PrecTerm,
-        },
-    );
-
-    table.insert(
-        TokenKind::Plus,
-        ParseRule {
-            prefix: ParseRuleFn::FnNone,
-            infix: ParseRuleFn::FnBinary,
-            precendence: Precedence::PrecTerm,
-        },
-    );
-
-    table.insert(TokenKind::Semicolon, ParseRuleNone.clone());
-
-    table.insert(
-        TokenKind::Slash,
-        ParseRule {
-            prefix: ParseRuleFn::FnNone,
-            infix: ParseRuleFn::FnBinary,
-            precendence: Precedence::PrecFactor,
-        },
-    );
-
-    table.insert(
-        TokenKind::Star,
-        ParseRule {
-            prefix: ParseRuleFn::FnNone,
-            infix: ParseRuleFn::FnBinary,
-            precendence: Precedence::PrecFactor,
-        },
-    );
-
-    table.insert(TokenKind::Bang, ParseRuleNone.clone());
-
-    table.insert(TokenKind::BangEqual, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Equal, ParseRuleNone.clone());
-
-    table.insert(TokenKind::EqualEqual, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Greater, ParseRuleNone.clone());
-
-    table.insert(TokenKind::GreaterEqual, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Less, ParseRuleNone.clone());
-
-    table.insert(TokenKind::LessEqual, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Identifier, ParseRuleNone.clone());
-
-    table.insert(TokenKind::String, ParseRuleNone.clone());
-
-    table.insert(
-        TokenKind::Number,
-        ParseRule {
-            prefix: ParseRuleFn::FnNumber,
-            infix: ParseRuleFn::FnNone,
-            precendence: Precedence::PrecNone,
-        },
-    );
-
-    table.insert(TokenKind::And, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Class, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Else, ParseRuleNone.clone());
-
-    table.insert(TokenKind::False, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Fun, ParseRuleNone.clone());
-
-    table.insert(TokenKind::For, ParseRuleNone.clone());
-
-    table.insert(TokenKind::If, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Nil, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Or, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Print, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Return, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Super, ParseRuleNone.clone());
-
-    table.insert(TokenKind::This, ParseRuleNone.clone());
-
-    table.insert(TokenKind::True, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Var, ParseRuleNone.clone());
-
-    table.insert(TokenKind::While, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Err, ParseRuleNone.clone());
-
-    table.insert(TokenKind::Eof, ParseRuleNone.clone());
-
-    return table;
-}
+static PARSE_RULES: [ParseRule; TokenKind::Eof as usize + 1] = [
+    // LeftParen
+    ParseRule {
+        prefix: ParseRuleFn::FnGrouping,
+        infix: ParseRuleFn::FnCall,
+        precendence: Precedence::PrecCall,
+    },
+    // RightParen
+    PARSE_RULE_NONE,
+    // LeftBrace
+    PARSE_RULE_NONE,
+    // RightBrace
+    PARSE_RULE_NONE,
+    // Comma
+    PARSE_RULE_NONE,
+    // Dot
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnDot,
+        precendence: Precedence::PrecCall,
+    },
+    // Minus
+    ParseRule {
+        prefix: ParseRuleFn::FnUnary,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecTerm,
+    },
+    // Plus
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecTerm,
+    },
+    // Semicolon
+    PARSE_RULE_NONE,
+    // Slash
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecFactor,
+    },
+    // Star
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecFactor,
+    },
+    // Bang
+    ParseRule {
+        prefix: ParseRuleFn::FnUnary,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // BangEqual
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecEquality,
+    },
+    // Equal
+    PARSE_RULE_NONE,
+    // EqualEqual
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecEquality,
+    },
+    // Greater
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecComparison,
+    },
+    // GreaterEqual
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecComparison,
+    },
+    // Less
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecComparison,
+    },
+    // LessEqual
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnBinary,
+        precendence: Precedence::PrecComparison,
+    },
+    // Identifier
+    ParseRule {
+        prefix: ParseRuleFn::FnVariable,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // String
+    ParseRule {
+        prefix: ParseRuleFn::FnString,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // Number
+    ParseRule {
+        prefix: ParseRuleFn::FnNumber,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // And
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnAnd,
+        precendence: Precedence::PrecAnd,
+    },
+    // Class
+    PARSE_RULE_NONE,
+    // Else
+    PARSE_RULE_NONE,
+    // False
+    ParseRule {
+        prefix: ParseRuleFn::FnLiteral,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // Fun
+    PARSE_RULE_NONE,
+    // For
+    PARSE_RULE_NONE,
+    // If
+    PARSE_RULE_NONE,
+    // Nil
+    ParseRule {
+        prefix: ParseRuleFn::FnLiteral,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // Or
+    ParseRule {
+        prefix: ParseRuleFn::FnNone,
+        infix: ParseRuleFn::FnOr,
+        precendence: Precedence::PrecOr,
+    },
+    // Print
+    PARSE_RULE_NONE,
+    // Return
+    PARSE_RULE_NONE,
+    // Super
+    ParseRule {
+        prefix: ParseRuleFn::FnSuper,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // This
+    ParseRule {
+        prefix: ParseRuleFn::FnThis,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // True
+    ParseRule {
+        prefix: ParseRuleFn::FnLiteral,
+        infix: ParseRuleFn::FnNone,
+        precendence: Precedence::PrecNone,
+    },
+    // Var
+    PARSE_RULE_NONE,
+    // While
+    PARSE_RULE_NONE,
+    // Err
+    PARSE_RULE_NONE,
+    // Eof
+    PARSE_RULE_NONE,
+];
 
 #[derive(Clone, Copy)]
 pub struct ParseRule {
@@ -209,56 +245,141 @@ pub struct ParseRule {
     precendence: Precedence,
 }
 
-pub trait Parser {
-    fn compile(&mut self, chunk: Chunk) -> CompilerResult;
+pub(crate) trait Parser {
+    fn compile(&mut self) -> CompilerResult;
+    fn init_function_compiler(&mut self, kind: FunctionType);
+    fn current_compiler(&mut self) -> &mut FunctionCompiler;
     fn expression(&mut self);
+    fn declaration(&mut self);
+    fn class_declaration(&mut self);
+    fn method(&mut self);
+    fn fun_declaration(&mut self);
+    fn function(&mut self, kind: FunctionType);
+    fn var_declaration(&mut self);
+    fn statement(&mut self);
+    fn block(&mut self);
+    fn begin_scope(&mut self);
+    fn end_scope(&mut self);
+    fn print_statement(&mut self);
+    fn return_statement(&mut self);
+    fn if_statement(&mut self);
+    fn while_statement(&mut self);
+    fn for_statement(&mut self);
+    fn expression_statement(&mut self);
+    fn synchronize(&mut self);
     fn advance(&mut self);
     fn consume(&mut self, kind: TokenKind, message: &str);
+    fn check(&mut self, kind: TokenKind) -> bool;
+    fn match_token(&mut self, kind: TokenKind) -> bool;
     fn error_at_current(&mut self, message: String);
     fn error_at(&mut self, token: Token, message: String);
     fn error(&mut self, message: String);
     fn emit_byte(&mut self, byte: u8);
+    fn emit_byte_at(&mut self, byte: u8, token: Token);
     fn emit_bytes(&mut self, byte1: u8, byte2: u8);
+    fn emit_operand(&mut self, instruction: OpCode, operand: usize);
+    fn emit_operand_at(&mut self, instruction: OpCode, operand: usize, token: Token);
+    fn emit_loop(&mut self, loop_start: usize);
+    fn emit_jump(&mut self, instruction: OpCode) -> usize;
+    fn patch_jump(&mut self, offset: usize);
     fn emit_return(&mut self);
-    fn end_compiler(&mut self);
+    fn end_compiler(&mut self) -> *mut Obj;
     fn current_chunk(&mut self) -> &mut Chunk;
     fn emit_constant(&mut self, value: Value);
-    fn make_constant(&mut self, value: Value) -> u8;
+    fn make_constant(&mut self, value: Value) -> usize;
+    fn identifier_constant(&mut self, name: Token) -> usize;
+    fn parse_variable(&mut self, message: &str) -> usize;
+    fn define_variable(&mut self, global: usize);
+    fn declare_variable(&mut self);
+    fn add_local(&mut self, name: Token);
+    fn mark_initialized(&mut self);
+    fn resolve_local(&mut self, compiler: usize, name: Token) -> Option<u8>;
+    fn add_upvalue(&mut self, compiler: usize, index: u8, is_local: bool) -> u8;
+    fn resolve_upvalue(&mut self, compiler: usize, name: Token) -> Option<u8>;
+    fn lexeme(&self, token: Token) -> &str;
+    fn identifiers_equal(&self, a: Token, b: Token) -> bool;
     fn number(&mut self);
+    fn string(&mut self);
     fn binary(&mut self);
+    fn call(&mut self);
+    fn argument_list(&mut self) -> u8;
+    fn dot(&mut self, can_assign: bool);
+    fn this(&mut self);
+    fn super_(&mut self);
     fn unary(&mut self);
     fn grouping(&mut self);
+    fn literal(&mut self);
+    fn and(&mut self);
+    fn or(&mut self);
+    fn variable(&mut self, can_assign: bool);
+    fn named_variable(&mut self, name: Token, can_assign: bool);
     fn parse_precendence(&mut self, precendence: Precedence);
     fn get_rule(&mut self, kind: TokenKind) -> ParseRule;
-    fn translate(&mut self, rule: ParseRuleFn);
+    fn translate(&mut self, rule: ParseRuleFn, can_assign: bool);
 }
 
-pub struct Compiler {
-    had_error: bool,
+pub struct Compiler<'a> {
+    vm: &'a mut VirtualMachine,
+    diagnostics: Vec<Diagnostic>,
     panic_mode: bool,
     source: String,
-    chunk: Chunk,
     scanner: Scanner,
     previous: Token,
     current: Token,
+    compilers: Vec<FunctionCompiler>,
+    classes: Vec<ClassCompiler>,
+}
+
+pub struct ClassCompiler {
+    has_superclass: bool,
+}
+
+pub struct FunctionCompiler {
+    function: *mut Obj,
+    kind: FunctionType,
+    locals: Vec<Local>,
+    upvalues: Vec<Upvalue>,
+    scope_depth: i32,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FunctionType {
+    TypeFunction,
+    TypeInitializer,
+    TypeMethod,
+    TypeScript,
+}
+
+#[derive(Clone, Copy)]
+pub struct Local {
+    name: Token,
+    depth: i32,
+    is_captured: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct Upvalue {
+    index: u8,
+    is_local: bool,
 }
 
 pub struct CompilerResult {
-    pub chunk: Chunk,
+    pub(crate) function: *mut Obj,
     pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
 }
 
-impl Parser for Compiler {
-    fn compile(&mut self, chunk: Chunk) -> CompilerResult {
-        self.chunk = chunk;
+impl Parser for Compiler<'_> {
+    fn compile(&mut self) -> CompilerResult {
+        self.init_function_compiler(FunctionType::TypeScript);
 
         self.advance();
 
-        self.expression();
-
-        self.consume(TokenKind::Eof, "Expect end of expression.");
+        while !self.match_token(TokenKind::Eof) {
+            self.declaration();
+        }
 
-        self.end_compiler();
+        let function = self.end_compiler();
 
         // let mut line = 0;
 
@@ -280,16 +401,444 @@ impl Parser for Compiler {
         //     }
         // }
 
+        let success = !self
+            .diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::SeverityError);
+
         CompilerResult {
-            chunk: copy_chunk(&self.chunk),
-            success: !self.had_error,
+            function,
+            success,
+            diagnostics: std::mem::take(&mut self.diagnostics),
+        }
+    }
+
+    fn init_function_compiler(&mut self, kind: FunctionType) {
+        let function = new_function(self.vm);
+
+        self.vm.compiler_roots.push(function);
+
+        if kind != FunctionType::TypeScript {
+            let name = self.lexeme(self.previous).to_string();
+
+            object_function(function).name = copy_string(self.vm, &name);
         }
+
+        let mut compiler = FunctionCompiler {
+            function,
+            kind,
+            locals: Vec::new(),
+            upvalues: Vec::new(),
+            scope_depth: 0,
+        };
+
+        let receiver = match kind {
+            FunctionType::TypeInitializer | FunctionType::TypeMethod => TokenKind::This,
+            _ => TokenKind::Identifier,
+        };
+
+        compiler.locals.push(Local {
+            name: synthetic_token(receiver),
+            depth: 0,
+            is_captured: false,
+        });
+
+        self.compilers.push(compiler);
+    }
+
+    fn current_compiler(&mut self) -> &mut FunctionCompiler {
+        return self.compilers.last_mut().unwrap();
     }
 
     fn expression(&mut self) {
         self.parse_precendence(Precedence::PrecAssignment);
     }
 
+    fn declaration(&mut self) {
+        if self.match_token(TokenKind::Class) {
+            self.class_declaration();
+        } else if self.match_token(TokenKind::Fun) {
+            self.fun_declaration();
+        } else if self.match_token(TokenKind::Var) {
+            self.var_declaration();
+        } else {
+            self.statement();
+        }
+
+        if self.panic_mode {
+            self.synchronize();
+        }
+    }
+
+    fn class_declaration(&mut self) {
+        self.consume(TokenKind::Identifier, "Expect class name.");
+
+        let class_name = self.previous;
+
+        let name_constant = self.identifier_constant(self.previous);
+
+        self.declare_variable();
+
+        self.emit_operand(OpCode::OpClass, name_constant);
+
+        self.define_variable(name_constant);
+
+        self.classes.push(ClassCompiler {
+            has_superclass: false,
+        });
+
+        if self.match_token(TokenKind::Less) {
+            self.consume(TokenKind::Identifier, "Expect superclass name.");
+
+            self.variable(false);
+
+            if self.identifiers_equal(class_name, self.previous) {
+                self.error("A class can't inherit from itself.".to_string());
+            }
+
+            self.begin_scope();
+
+            self.add_local(synthetic_token(TokenKind::Super));
+
+            self.define_variable(0);
+
+            self.named_variable(class_name, false);
+
+            self.emit_byte(opcode_to_u8(OpCode::OpInherit));
+
+            self.classes.last_mut().unwrap().has_superclass = true;
+        }
+
+        self.named_variable(class_name, false);
+
+        self.consume(TokenKind::LeftBrace, "Expect '{' before class body.");
+
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            self.method();
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after class body.");
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        if self.classes.pop().unwrap().has_superclass {
+            self.end_scope();
+        }
+    }
+
+    fn method(&mut self) {
+        self.consume(TokenKind::Identifier, "Expect method name.");
+
+        let constant = self.identifier_constant(self.previous);
+
+        let kind = if self.lexeme(self.previous) == "init" {
+            FunctionType::TypeInitializer
+        } else {
+            FunctionType::TypeMethod
+        };
+
+        self.function(kind);
+
+        self.emit_operand(OpCode::OpMethod, constant);
+    }
+
+    fn fun_declaration(&mut self) {
+        let global = self.parse_variable("Expect function name.");
+
+        self.mark_initialized();
+
+        self.function(FunctionType::TypeFunction);
+
+        self.define_variable(global);
+    }
+
+    fn function(&mut self, kind: FunctionType) {
+        self.init_function_compiler(kind);
+
+        self.begin_scope();
+
+        self.consume(TokenKind::LeftParen, "Expect '(' after function name.");
+
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                let function = object_function(self.current_compiler().function);
+
+                function.arity += 1;
+
+                if function.arity > 255 {
+                    self.error_at_current("Can't have more than 255 parameters.".to_string());
+                }
+
+                let constant = self.parse_variable("Expect parameter name.");
+
+                self.define_variable(constant);
+
+                if !self.match_token(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenKind::RightParen, "Expect ')' after parameters.");
+
+        self.consume(TokenKind::LeftBrace, "Expect '{' before function body.");
+
+        self.block();
+
+        let upvalues = self.current_compiler().upvalues.clone();
+
+        let function = self.end_compiler();
+
+        let constant = self.make_constant(Value::ValObj(function));
+
+        self.emit_operand(OpCode::OpClosure, constant);
+
+        for upvalue in upvalues {
+            self.emit_byte(if upvalue.is_local { 1 } else { 0 });
+            self.emit_byte(upvalue.index);
+        }
+    }
+
+    fn var_declaration(&mut self) {
+        let global = self.parse_variable("Expect variable name.");
+
+        if self.match_token(TokenKind::Equal) {
+            self.expression();
+        } else {
+            self.emit_byte(opcode_to_u8(OpCode::OpNil));
+        }
+
+        self.consume(
+            TokenKind::Semicolon,
+            "Expect ';' after variable declaration.",
+        );
+
+        self.define_variable(global);
+    }
+
+    fn statement(&mut self) {
+        if self.match_token(TokenKind::Print) {
+            self.print_statement();
+        } else if self.match_token(TokenKind::Return) {
+            self.return_statement();
+        } else if self.match_token(TokenKind::If) {
+            self.if_statement();
+        } else if self.match_token(TokenKind::While) {
+            self.while_statement();
+        } else if self.match_token(TokenKind::For) {
+            self.for_statement();
+        } else if self.match_token(TokenKind::LeftBrace) {
+            self.begin_scope();
+
+            self.block();
+
+            self.end_scope();
+        } else {
+            self.expression_statement();
+        }
+    }
+
+    fn block(&mut self) {
+        while !self.check(TokenKind::RightBrace) && !self.check(TokenKind::Eof) {
+            self.declaration();
+        }
+
+        self.consume(TokenKind::RightBrace, "Expect '}' after block.");
+    }
+
+    fn begin_scope(&mut self) {
+        self.current_compiler().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current_compiler().scope_depth -= 1;
+
+        loop {
+            let compiler = self.current_compiler();
+
+            match compiler.locals.last() {
+                Some(local) if local.depth > compiler.scope_depth => {
+                    let instruction = if local.is_captured {
+                        OpCode::OpCloseUpvalue
+                    } else {
+                        OpCode::OpPop
+                    };
+
+                    compiler.locals.pop();
+
+                    self.emit_byte(opcode_to_u8(instruction));
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn print_statement(&mut self) {
+        self.expression();
+
+        self.consume(TokenKind::Semicolon, "Expect ';' after value.");
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPrint));
+    }
+
+    fn return_statement(&mut self) {
+        if self.current_compiler().kind == FunctionType::TypeScript {
+            self.error("Can't return from top-level code.".to_string());
+        }
+
+        if self.match_token(TokenKind::Semicolon) {
+            self.emit_return();
+        } else {
+            if self.current_compiler().kind == FunctionType::TypeInitializer {
+                self.error("Can't return a value from an initializer.".to_string());
+            }
+
+            self.expression();
+
+            self.consume(TokenKind::Semicolon, "Expect ';' after return value.");
+
+            self.emit_byte(opcode_to_u8(OpCode::OpReturn));
+        }
+    }
+
+    fn if_statement(&mut self) {
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.");
+
+        self.expression();
+
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+
+        let then_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        self.statement();
+
+        let else_jump = self.emit_jump(OpCode::OpJump);
+
+        self.patch_jump(then_jump);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        if self.match_token(TokenKind::Else) {
+            self.statement();
+        }
+
+        self.patch_jump(else_jump);
+    }
+
+    fn while_statement(&mut self) {
+        let loop_start = self.current_chunk().code.len();
+
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'while'.");
+
+        self.expression();
+
+        self.consume(TokenKind::RightParen, "Expect ')' after condition.");
+
+        let exit_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        self.statement();
+
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+    }
+
+    fn for_statement(&mut self) {
+        self.begin_scope();
+
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.");
+
+        if self.match_token(TokenKind::Semicolon) {
+            // No initializer.
+        } else if self.match_token(TokenKind::Var) {
+            self.var_declaration();
+        } else {
+            self.expression_statement();
+        }
+
+        let mut loop_start = self.current_chunk().code.len();
+
+        let mut exit_jump = None;
+
+        if !self.match_token(TokenKind::Semicolon) {
+            self.expression();
+
+            self.consume(TokenKind::Semicolon, "Expect ';' after loop condition.");
+
+            exit_jump = Some(self.emit_jump(OpCode::OpJumpIfFalse));
+
+            self.emit_byte(opcode_to_u8(OpCode::OpPop));
+        }
+
+        if !self.match_token(TokenKind::RightParen) {
+            let body_jump = self.emit_jump(OpCode::OpJump);
+
+            let increment_start = self.current_chunk().code.len();
+
+            self.expression();
+
+            self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+            self.consume(TokenKind::RightParen, "Expect ')' after for clauses.");
+
+            self.emit_loop(loop_start);
+
+            loop_start = increment_start;
+
+            self.patch_jump(body_jump);
+        }
+
+        self.statement();
+
+        self.emit_loop(loop_start);
+
+        if let Some(exit_jump) = exit_jump {
+            self.patch_jump(exit_jump);
+
+            self.emit_byte(opcode_to_u8(OpCode::OpPop));
+        }
+
+        self.end_scope();
+    }
+
+    fn expression_statement(&mut self) {
+        self.expression();
+
+        self.consume(TokenKind::Semicolon, "Expect ';' after expression.");
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+    }
+
+    fn synchronize(&mut self) {
+        self.panic_mode = false;
+
+        while self.current.kind != TokenKind::Eof {
+            if self.previous.kind == TokenKind::Semicolon {
+                return;
+            }
+
+            match self.current.kind {
+                TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Var
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Print
+                | TokenKind::Return => return,
+                _ => {}
+            }
+
+            self.advance();
+        }
+    }
+
     fn advance(&mut self) {
         if self.scanner.is_at_end() && self.current.kind == TokenKind::Eof {
             return;
@@ -298,19 +847,13 @@ impl Parser for Compiler {
         self.previous = self.current;
 
         loop {
-
             self.current = self.scanner.next_token();
 
-            println!("{:?}", self.current.kind);
-
             if self.current.kind != TokenKind::Err {
                 break;
             }
 
-            self.error_at_current(
-                self.source[self.current.start..self.current.start + self.current.length]
-                    .to_string(),
-            );
+            self.error_at_current(self.scanner.error_message().to_string());
         }
     }
 
@@ -323,6 +866,20 @@ impl Parser for Compiler {
         self.error_at_current(message.to_string());
     }
 
+    fn check(&mut self, kind: TokenKind) -> bool {
+        return self.current.kind == kind;
+    }
+
+    fn match_token(&mut self, kind: TokenKind) -> bool {
+        if !self.check(kind) {
+            return false;
+        }
+
+        self.advance();
+
+        return true;
+    }
+
     fn error_at_current(&mut self, message: String) {
         self.error_at(self.current, message);
     }
@@ -334,22 +891,21 @@ impl Parser for Compiler {
 
         self.panic_mode = true;
 
-        print!("[line {}] Error", token.line);
-
-        match token.kind {
-            TokenKind::Eof => print!(" at end"),
-            TokenKind::Err => {}
-            _ => {
-                print!(
-                    " at '{}'",
-                    self.source[token.start..token.start + token.length].to_string()
-                );
-            }
-        }
-
-        println!(": {}", message);
+        let location = match token.kind {
+            TokenKind::Eof => " at end".to_string(),
+            TokenKind::Err => String::new(),
+            _ => format!(" at '{}'", self.lexeme(token)),
+        };
 
-        self.had_error = true;
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::SeverityError,
+            message,
+            line: token.line,
+            col: token.col,
+            start: token.start,
+            length: token.length,
+            location,
+        });
     }
 
     fn error(&mut self, message: String) {
@@ -357,41 +913,270 @@ impl Parser for Compiler {
     }
 
     fn emit_byte(&mut self, byte: u8) {
-        write_chunk_u8(&mut self.chunk, byte, self.previous.line as i32);
+        self.emit_byte_at(byte, self.previous);
+    }
+
+    fn emit_byte_at(&mut self, byte: u8, token: Token) {
+        let line = token.line as i32;
+        let col = token.col as i32;
+        let length = token.length as i32;
+
+        write_chunk_u8(self.current_chunk(), byte, line, col, length);
     }
 
     fn emit_bytes(&mut self, byte1: u8, byte2: u8) {
-        write_chunk_u8(&mut self.chunk, byte1, self.previous.line as i32);
-        write_chunk_u8(&mut self.chunk, byte2, self.previous.line as i32);
+        self.emit_byte(byte1);
+        self.emit_byte(byte2);
+    }
+
+    fn emit_operand(&mut self, instruction: OpCode, operand: usize) {
+        self.emit_operand_at(instruction, operand, self.previous);
+    }
+
+    fn emit_operand_at(&mut self, instruction: OpCode, operand: usize, token: Token) {
+        if operand <= u8::MAX as usize {
+            self.emit_byte_at(opcode_to_u8(instruction), token);
+            self.emit_byte_at(operand as u8, token);
+
+            return;
+        }
+
+        let long = long_opcode(instruction).expect("Operand does not fit in one byte.");
+
+        self.emit_byte_at(opcode_to_u8(long), token);
+        self.emit_byte_at(((operand >> 16) & 0xff) as u8, token);
+        self.emit_byte_at(((operand >> 8) & 0xff) as u8, token);
+        self.emit_byte_at((operand & 0xff) as u8, token);
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.emit_byte(opcode_to_u8(OpCode::OpLoop));
+
+        let offset = self.current_chunk().code.len() - loop_start + 2;
+
+        if offset > u16::MAX as usize {
+            self.error("Loop body too large.".to_string());
+        }
+
+        self.emit_byte(((offset >> 8) & 0xff) as u8);
+        self.emit_byte((offset & 0xff) as u8);
+    }
+
+    fn emit_jump(&mut self, instruction: OpCode) -> usize {
+        self.emit_byte(opcode_to_u8(instruction));
+        self.emit_byte(0xff);
+        self.emit_byte(0xff);
+
+        return self.current_chunk().code.len() - 2;
+    }
+
+    fn patch_jump(&mut self, offset: usize) {
+        let jump = self.current_chunk().code.len() - offset - 2;
+
+        if jump > u16::MAX as usize {
+            self.error("Too much code to jump over.".to_string());
+        }
+
+        self.current_chunk().code[offset] = ((jump >> 8) & 0xff) as u8;
+        self.current_chunk().code[offset + 1] = (jump & 0xff) as u8;
     }
 
     fn emit_return(&mut self) {
-        self.emit_byte(op_code_table()[&OpCode::OpReturn]);
+        if self.current_compiler().kind == FunctionType::TypeInitializer {
+            self.emit_bytes(opcode_to_u8(OpCode::OpGetLocal), 0);
+        } else {
+            self.emit_byte(opcode_to_u8(OpCode::OpNil));
+        }
+
+        self.emit_byte(opcode_to_u8(OpCode::OpReturn));
     }
 
-    fn end_compiler(&mut self) {
+    fn end_compiler(&mut self) -> *mut Obj {
         self.emit_return();
+
+        self.vm.compiler_roots.pop();
+
+        let function = self.compilers.pop().unwrap().function;
+
+        track_growth(self.vm, 0, chunk_size(&object_function(function).chunk));
+
+        return function;
     }
 
     fn current_chunk(&mut self) -> &mut Chunk {
-        &mut self.chunk
+        &mut object_function(self.current_compiler().function).chunk
     }
 
     fn emit_constant(&mut self, value: Value) {
         let constant = self.make_constant(value);
 
-        self.emit_bytes(opcode_to_u8(OpCode::OpConstant), constant)
+        self.emit_operand(OpCode::OpConstant, constant);
     }
 
-    fn make_constant(&mut self, value: Value) -> u8 {
-        let constant = add_constant(&mut self.chunk, value);
+    fn make_constant(&mut self, value: Value) -> usize {
+        let constant = add_constant(self.current_chunk(), value);
 
-        if constant > 255 {
+        if constant >= 1 << 24 {
             self.error("Too many constants in one chunk.".to_string());
             return 0;
         }
 
-        return constant as u8;
+        return constant;
+    }
+
+    fn identifier_constant(&mut self, name: Token) -> usize {
+        let chars = self.lexeme(name).to_string();
+
+        let value = copy_string(self.vm, &chars);
+
+        return self.make_constant(Value::ValObj(value));
+    }
+
+    fn parse_variable(&mut self, message: &str) -> usize {
+        self.consume(TokenKind::Identifier, message);
+
+        self.declare_variable();
+
+        if self.current_compiler().scope_depth > 0 {
+            return 0;
+        }
+
+        return self.identifier_constant(self.previous);
+    }
+
+    fn define_variable(&mut self, global: usize) {
+        if self.current_compiler().scope_depth > 0 {
+            self.mark_initialized();
+
+            return;
+        }
+
+        self.emit_operand(OpCode::OpDefineGlobal, global);
+    }
+
+    fn declare_variable(&mut self) {
+        let scope_depth = self.current_compiler().scope_depth;
+
+        if scope_depth == 0 {
+            return;
+        }
+
+        let name = self.previous;
+
+        let compiler = self.compilers.last().unwrap();
+
+        let duplicate = compiler
+            .locals
+            .iter()
+            .rev()
+            .take_while(|local| local.depth == -1 || local.depth >= scope_depth)
+            .any(|local| self.identifiers_equal(name, local.name));
+
+        if duplicate {
+            self.error("Already a variable with this name in this scope.".to_string());
+        }
+
+        self.add_local(name);
+    }
+
+    fn add_local(&mut self, name: Token) {
+        if self.current_compiler().locals.len() == UINT8_COUNT {
+            self.error("Too many local variables in function.".to_string());
+
+            return;
+        }
+
+        self.current_compiler().locals.push(Local {
+            name,
+            depth: -1,
+            is_captured: false,
+        });
+    }
+
+    fn mark_initialized(&mut self) {
+        let compiler = self.current_compiler();
+
+        if compiler.scope_depth == 0 {
+            return;
+        }
+
+        if let Some(local) = compiler.locals.last_mut() {
+            local.depth = compiler.scope_depth;
+        }
+    }
+
+    fn resolve_local(&mut self, compiler: usize, name: Token) -> Option<u8> {
+        let compiler = &self.compilers[compiler];
+
+        let slot = compiler
+            .locals
+            .iter()
+            .rposition(|local| self.identifiers_equal(name, local.name));
+
+        if let Some(slot) = slot {
+            if compiler.locals[slot].depth == -1 {
+                self.error("Can't read local variable in its own initializer.".to_string());
+            }
+
+            return Some(slot as u8);
+        }
+
+        return None;
+    }
+
+    fn add_upvalue(&mut self, compiler: usize, index: u8, is_local: bool) -> u8 {
+        let upvalues = &self.compilers[compiler].upvalues;
+
+        if let Some(existing) = upvalues
+            .iter()
+            .position(|upvalue| upvalue.index == index && upvalue.is_local == is_local)
+        {
+            return existing as u8;
+        }
+
+        if upvalues.len() == UINT8_COUNT {
+            self.error("Too many closure variables in function.".to_string());
+
+            return 0;
+        }
+
+        let compiler = &mut self.compilers[compiler];
+
+        compiler.upvalues.push(Upvalue { index, is_local });
+
+        object_function(compiler.function).upvalue_count = compiler.upvalues.len();
+
+        return (compiler.upvalues.len() - 1) as u8;
+    }
+
+    fn resolve_upvalue(&mut self, compiler: usize, name: Token) -> Option<u8> {
+        if compiler == 0 {
+            return None;
+        }
+
+        if let Some(local) = self.resolve_local(compiler - 1, name) {
+            self.compilers[compiler - 1].locals[local as usize].is_captured = true;
+
+            return Some(self.add_upvalue(compiler, local, true));
+        }
+
+        if let Some(upvalue) = self.resolve_upvalue(compiler - 1, name) {
+            return Some(self.add_upvalue(compiler, upvalue, false));
+        }
+
+        return None;
+    }
+
+    fn lexeme(&self, token: Token) -> &str {
+        match token.kind {
+            TokenKind::This => "this",
+            TokenKind::Super => "super",
+            _ => &self.source[token.start..token.start + token.length],
+        }
+    }
+
+    fn identifiers_equal(&self, a: Token, b: Token) -> bool {
+        return self.lexeme(a) == self.lexeme(b);
     }
 
     fn number(&mut self) {
@@ -400,42 +1185,130 @@ impl Parser for Compiler {
             .parse::<f64>()
             .unwrap();
 
-        self.emit_constant(value);
+        self.emit_constant(Value::ValNumber(value));
+    }
+
+    fn string(&mut self) {
+        let chars =
+            &self.source[self.previous.start + 1..self.previous.start + self.previous.length - 1];
+
+        let value = copy_string(self.vm, chars);
+
+        self.emit_constant(Value::ValObj(value));
+    }
+
+    fn call(&mut self) {
+        let arg_count = self.argument_list();
+
+        self.emit_bytes(opcode_to_u8(OpCode::OpCall), arg_count);
+    }
+
+    fn argument_list(&mut self) -> u8 {
+        let mut arg_count: usize = 0;
+
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                self.expression();
+
+                if arg_count == 255 {
+                    self.error("Can't have more than 255 arguments.".to_string());
+                }
+
+                arg_count += 1;
+
+                if !self.match_token(TokenKind::Comma) {
+                    break;
+                }
+            }
+        }
+
+        self.consume(TokenKind::RightParen, "Expect ')' after arguments.");
+
+        return arg_count as u8;
+    }
+
+    fn dot(&mut self, can_assign: bool) {
+        self.consume(TokenKind::Identifier, "Expect property name after '.'.");
+
+        let name = self.identifier_constant(self.previous);
+
+        if can_assign && self.match_token(TokenKind::Equal) {
+            self.expression();
+
+            self.emit_operand(OpCode::OpSetProperty, name);
+        } else {
+            self.emit_operand(OpCode::OpGetProperty, name);
+        }
+    }
+
+    fn this(&mut self) {
+        if self.classes.is_empty() {
+            self.error("Can't use 'this' outside of a class.".to_string());
+
+            return;
+        }
+
+        self.variable(false);
+    }
+
+    fn super_(&mut self) {
+        match self.classes.last() {
+            None => self.error("Can't use 'super' outside of a class.".to_string()),
+            Some(class) if !class.has_superclass => {
+                self.error("Can't use 'super' in a class with no superclass.".to_string())
+            }
+            _ => {}
+        }
+
+        self.consume(TokenKind::Dot, "Expect '.' after 'super'.");
+        self.consume(TokenKind::Identifier, "Expect superclass method name.");
+
+        let name = self.identifier_constant(self.previous);
+
+        self.named_variable(synthetic_token(TokenKind::This), false);
+        self.named_variable(synthetic_token(TokenKind::Super), false);
+
+        self.emit_operand(OpCode::OpGetSuper, name);
     }
 
     fn unary(&mut self) {
-        let kind = self.previous.kind;
+        let operator = self.previous;
 
         self.parse_precendence(Precedence::PrecUnary);
 
-        match kind {
-            // TokenKind::Bang => self.emit_byte(opcode_to_u8(OpCode::OpNot)),
-            TokenKind::Minus => self.emit_byte(opcode_to_u8(OpCode::OpNegate)),
+        match operator.kind {
+            TokenKind::Bang => self.emit_byte_at(opcode_to_u8(OpCode::OpNot), operator),
+            TokenKind::Minus => self.emit_byte_at(opcode_to_u8(OpCode::OpNegate), operator),
             _ => {}
         }
     }
 
     fn binary(&mut self) {
-        let kind = self.previous.kind;
-
-        let rule = self.get_rule(kind);
-
-        self.parse_precendence(order_to_precendence(
-            precendence_to_order(rule.precendence) + 1,
-        ));
-
-        let instruction = match kind {
-            TokenKind::Plus => opcode_to_u8(OpCode::OpAdd),
-            TokenKind::Minus => opcode_to_u8(OpCode::OpSubtract),
-            TokenKind::Star => opcode_to_u8(OpCode::OpMultiply),
-            TokenKind::Slash => opcode_to_u8(OpCode::OpDivide),
-            // TokenKind::Equal => opcode_to_u8(OpCode::OpEqual),
-            // TokenKind::BangEqual => opcode_to_u8(OpCode::OpNotEqual),
-            // TokenKind::Greater => opcode_to_u8(OpCode::OpGreater),
+        let operator = self.previous;
+
+        let rule = self.get_rule(operator.kind);
+
+        self.parse_precendence(order_to_precendence(rule.precendence as u8 + 1));
+
+        let (instruction, negate) = match operator.kind {
+            TokenKind::BangEqual => (OpCode::OpEqual, true),
+            TokenKind::EqualEqual => (OpCode::OpEqual, false),
+            TokenKind::Greater => (OpCode::OpGreater, false),
+            TokenKind::GreaterEqual => (OpCode::OpLess, true),
+            TokenKind::Less => (OpCode::OpLess, false),
+            TokenKind::LessEqual => (OpCode::OpGreater, true),
+            TokenKind::Plus => (OpCode::OpAdd, false),
+            TokenKind::Minus => (OpCode::OpSubtract, false),
+            TokenKind::Star => (OpCode::OpMultiply, false),
+            TokenKind::Slash => (OpCode::OpDivide, false),
             _ => panic!(),
         };
 
-        self.emit_byte(instruction);
+        self.emit_byte_at(opcode_to_u8(instruction), operator);
+
+        if negate {
+            self.emit_byte_at(opcode_to_u8(OpCode::OpNot), operator);
+        }
     }
 
     fn grouping(&mut self) {
@@ -444,6 +1317,67 @@ impl Parser for Compiler {
         self.consume(TokenKind::RightParen, "Expect ')' after expression.");
     }
 
+    fn literal(&mut self) {
+        match self.previous.kind {
+            TokenKind::False => self.emit_byte(opcode_to_u8(OpCode::OpFalse)),
+            TokenKind::Nil => self.emit_byte(opcode_to_u8(OpCode::OpNil)),
+            TokenKind::True => self.emit_byte(opcode_to_u8(OpCode::OpTrue)),
+            _ => {}
+        }
+    }
+
+    fn and(&mut self) {
+        let end_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        self.parse_precendence(Precedence::PrecAnd);
+
+        self.patch_jump(end_jump);
+    }
+
+    fn or(&mut self) {
+        let else_jump = self.emit_jump(OpCode::OpJumpIfFalse);
+        let end_jump = self.emit_jump(OpCode::OpJump);
+
+        self.patch_jump(else_jump);
+
+        self.emit_byte(opcode_to_u8(OpCode::OpPop));
+
+        self.parse_precendence(Precedence::PrecOr);
+
+        self.patch_jump(end_jump);
+    }
+
+    fn variable(&mut self, can_assign: bool) {
+        self.named_variable(self.previous, can_assign);
+    }
+
+    fn named_variable(&mut self, name: Token, can_assign: bool) {
+        let compiler = self.compilers.len() - 1;
+
+        let (get_op, set_op, arg) = if let Some(arg) = self.resolve_local(compiler, name) {
+            (OpCode::OpGetLocal, OpCode::OpSetLocal, arg as usize)
+        } else if let Some(arg) = self.resolve_upvalue(compiler, name) {
+            (OpCode::OpGetUpvalue, OpCode::OpSetUpvalue, arg as usize)
+        } else {
+            let arg = self.identifier_constant(name);
+
+            (OpCode::OpGetGlobal, OpCode::OpSetGlobal, arg)
+        };
+
+        // Synthetic `this` and `super` tokens have no position of their own.
+        let at = if name.line == 0 { self.previous } else { name };
+
+        if can_assign && self.match_token(TokenKind::Equal) {
+            self.expression();
+
+            self.emit_operand_at(set_op, arg, at);
+        } else {
+            self.emit_operand_at(get_op, arg, at);
+        }
+    }
+
     fn parse_precendence(&mut self, precendence: Precedence) {
         self.advance();
 
@@ -455,38 +1389,58 @@ impl Parser for Compiler {
             return;
         }
 
-        self.translate(prefix_rule);
+        let can_assign = precendence as u8 <= Precedence::PrecAssignment as u8;
+
+        self.translate(prefix_rule, can_assign);
 
-        while precendence_to_order(precendence) <= precendence_to_order(self.get_rule(self.current.kind).precendence) {
+        while precendence as u8 <= self.get_rule(self.current.kind).precendence as u8 {
             self.advance();
 
             let infix_rule = self.get_rule(self.previous.kind).infix;
-            
-            self.translate(infix_rule);
+
+            self.translate(infix_rule, can_assign);
+        }
+
+        if can_assign && self.match_token(TokenKind::Equal) {
+            self.error("Invalid assignment target.".to_string());
         }
     }
 
     fn get_rule(&mut self, kind: TokenKind) -> ParseRule {
-        return fn_table()[&kind];
+        return PARSE_RULES[kind as usize];
     }
 
-    fn translate(&mut self, rule: ParseRuleFn) {
+    fn translate(&mut self, rule: ParseRuleFn, can_assign: bool) {
         match rule {
-            ParseRuleFn::FnNone => {},
+            ParseRuleFn::FnNone => {}
             ParseRuleFn::FnNumber => self.number(),
             ParseRuleFn::FnUnary => self.unary(),
             ParseRuleFn::FnBinary => self.binary(),
             ParseRuleFn::FnGrouping => self.grouping(),
+            ParseRuleFn::FnLiteral => self.literal(),
+            ParseRuleFn::FnString => self.string(),
+            ParseRuleFn::FnVariable => self.variable(can_assign),
+            ParseRuleFn::FnAnd => self.and(),
+            ParseRuleFn::FnOr => self.or(),
+            ParseRuleFn::FnCall => self.call(),
+            ParseRuleFn::FnDot => self.dot(can_assign),
+            ParseRuleFn::FnThis => self.this(),
+            ParseRuleFn::FnSuper => self.super_(),
         }
     }
 }
 
-pub fn init_compiler(source: String) -> Compiler {
+/// Compiles `source` into a top-level script owned by `vm`.
+pub fn compile(source: String, vm: &mut VirtualMachine) -> CompilerResult {
+    return init_compiler(source, vm).compile();
+}
+
+pub(crate) fn init_compiler(source: String, vm: &mut VirtualMachine) -> Compiler<'_> {
     Compiler {
-        had_error: false,
+        vm,
+        diagnostics: Vec::new(),
         panic_mode: false,
         source: source.clone(),
-        chunk: init_chunk(),
         scanner: init_scanner(source),
         current: Token {
             kind: TokenKind::Eof,
@@ -502,5 +1456,17 @@ pub fn init_compiler(source: String) -> Compiler {
             line: 0,
             col: 0,
         },
+        compilers: Vec::new(),
+        classes: Vec::new(),
+    }
+}
+
+fn synthetic_token(kind: TokenKind) -> Token {
+    Token {
+        kind,
+        start: 0,
+        length: 0,
+        line: 0,
+        col: 0,
     }
 }
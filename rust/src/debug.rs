@@ -1,82 +1,261 @@
 use crate::chunk::*;
 use crate::common::*;
+use crate::object::*;
 use crate::value::*;
+use std::convert::TryFrom;
+
+/// Column width used for `line:col` when tracing execution, where the widest
+/// position in the chunk is not known up front.
+pub const TRACE_POSITION_WIDTH: usize = 7;
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct SourcePosition {
+    pub line: i32,
+    pub col: i32,
+}
 
 pub fn disassemble_chunk(chunk: &Chunk, name: &str) {
     println!("== {} ==", name);
 
-    let table = op_code_table();
+    let starts = chunk.column_starts().unwrap_or_default();
+
+    let line_width = chunk
+        .lines
+        .iter()
+        .map(|start| start.line.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let col_width = starts
+        .iter()
+        .map(|start| start.col.to_string().len())
+        .max()
+        .unwrap_or(1);
+
+    let padding = line_width + 1 + col_width;
+
+    let mut next_start = 0;
+    let mut col = 0;
+    let mut previous = None;
 
     let mut offset = 0;
 
     while offset < chunk.code.len() {
-        offset = disassemble_instruction(chunk, &mut offset, &table);
+        while next_start < starts.len() && starts[next_start].offset <= offset {
+            col = starts[next_start].col;
+
+            next_start += 1;
+        }
+
+        let position = SourcePosition {
+            line: chunk.get_line(offset),
+            col,
+        };
+
+        offset = disassemble_instruction(chunk, &mut offset, position, previous, padding);
+
+        previous = Some(position);
     }
 
     println!("==={}===", "=".repeat(name.len()));
 }
 
-pub fn disassemble_instruction(chunk: &Chunk, offset: &mut usize, table: &OpCodeTable) -> usize {
-    let padding = chunk
-        .lines
-        .iter()
-        .map(|l| l.to_string().len())
-        .max()
-        .unwrap();
-
-    if *offset > 0 && chunk.lines[*offset] == chunk.lines[*offset - 1] {
-        print!(
-            "{}  | ",
-            " ".repeat(padding - chunk.lines[*offset].to_string().len())
-        );
+/// Prints one instruction. `position` is where it came from in the source and
+/// `previous` is the position of the instruction before it, if any; a repeated
+/// position is printed as `|`.
+pub fn disassemble_instruction(
+    chunk: &Chunk,
+    offset: &mut usize,
+    position: SourcePosition,
+    previous: Option<SourcePosition>,
+    padding: usize,
+) -> usize {
+    if previous == Some(position) {
+        print!("{:>width$} ", "|", width = padding);
     } else {
-        print!(
-            "{}{} | ",
-            chunk.lines[*offset],
-            " ".repeat(padding - chunk.lines[*offset].to_string().len())
-        );
+        let text = format!("{}:{}", position.line, position.col);
+
+        print!("{:<width$} ", text, width = padding);
     }
 
     print!("{:04} ", offset);
 
-    let instruction = u8_to_opcode(chunk.code[*offset]).expect("Cannot convert u8 to OpCode.");
+    let instruction = match OpCode::try_from(chunk.code[*offset]) {
+        Ok(instruction) => instruction,
+        Err(byte) => {
+            println!("Unknown opcode {}", byte);
+
+            return *offset + 1;
+        }
+    };
 
     match instruction {
         OpCode::OpReturn => {
-            return simple_instruction("OP_RETURN", offset, table);
+            return simple_instruction("OP_RETURN", offset);
         }
         OpCode::OpNegate => {
-            return simple_instruction("OP_NEGATE", offset, table);
+            return simple_instruction("OP_NEGATE", offset);
         }
         OpCode::OpAdd => {
-            return simple_instruction("OP_ADD", offset, table);
+            return simple_instruction("OP_ADD", offset);
         }
         OpCode::OpSubtract => {
-            return simple_instruction("OP_SUBTRACT", offset, table);
+            return simple_instruction("OP_SUBTRACT", offset);
         }
         OpCode::OpMultiply => {
-            return simple_instruction("OP_MULTIPLY", offset, table);
+            return simple_instruction("OP_MULTIPLY", offset);
         }
         OpCode::OpDivide => {
-            return simple_instruction("OP_DIVIDE", offset, table);
+            return simple_instruction("OP_DIVIDE", offset);
         }
         OpCode::OpConstant => {
-            return constant_instruction("OP_CONSTANT", chunk, offset, table);
+            return constant_instruction("OP_CONSTANT", chunk, offset);
         }
-        _ => {
-            println!("Unknown opcode {:?}", instruction);
-            return *offset + 1;
+        OpCode::OpConstantLong => {
+            return constant_long_instruction("OP_CONSTANT_LONG", chunk, offset);
+        }
+        OpCode::OpNil => {
+            return simple_instruction("OP_NIL", offset);
+        }
+        OpCode::OpTrue => {
+            return simple_instruction("OP_TRUE", offset);
+        }
+        OpCode::OpFalse => {
+            return simple_instruction("OP_FALSE", offset);
+        }
+        OpCode::OpNot => {
+            return simple_instruction("OP_NOT", offset);
+        }
+        OpCode::OpEqual => {
+            return simple_instruction("OP_EQUAL", offset);
+        }
+        OpCode::OpGreater => {
+            return simple_instruction("OP_GREATER", offset);
+        }
+        OpCode::OpLess => {
+            return simple_instruction("OP_LESS", offset);
+        }
+        OpCode::OpPrint => {
+            return simple_instruction("OP_PRINT", offset);
+        }
+        OpCode::OpPop => {
+            return simple_instruction("OP_POP", offset);
+        }
+        OpCode::OpDefineGlobal => {
+            return constant_instruction("OP_DEFINE_GLOBAL", chunk, offset);
+        }
+        OpCode::OpGetGlobal => {
+            return constant_instruction("OP_GET_GLOBAL", chunk, offset);
+        }
+        OpCode::OpSetGlobal => {
+            return constant_instruction("OP_SET_GLOBAL", chunk, offset);
+        }
+        OpCode::OpGetLocal => {
+            return byte_instruction("OP_GET_LOCAL", chunk, offset);
+        }
+        OpCode::OpSetLocal => {
+            return byte_instruction("OP_SET_LOCAL", chunk, offset);
+        }
+        OpCode::OpJump => {
+            return jump_instruction("OP_JUMP", 1, chunk, offset);
+        }
+        OpCode::OpJumpIfFalse => {
+            return jump_instruction("OP_JUMP_IF_FALSE", 1, chunk, offset);
+        }
+        OpCode::OpLoop => {
+            return jump_instruction("OP_LOOP", -1, chunk, offset);
+        }
+        OpCode::OpCall => {
+            return byte_instruction("OP_CALL", chunk, offset);
+        }
+        OpCode::OpClosure => {
+            return closure_instruction("OP_CLOSURE", chunk, offset);
+        }
+        OpCode::OpGetUpvalue => {
+            return byte_instruction("OP_GET_UPVALUE", chunk, offset);
+        }
+        OpCode::OpSetUpvalue => {
+            return byte_instruction("OP_SET_UPVALUE", chunk, offset);
+        }
+        OpCode::OpCloseUpvalue => {
+            return simple_instruction("OP_CLOSE_UPVALUE", offset);
+        }
+        OpCode::OpClass => {
+            return constant_instruction("OP_CLASS", chunk, offset);
+        }
+        OpCode::OpGetProperty => {
+            return constant_instruction("OP_GET_PROPERTY", chunk, offset);
+        }
+        OpCode::OpSetProperty => {
+            return constant_instruction("OP_SET_PROPERTY", chunk, offset);
+        }
+        OpCode::OpMethod => {
+            return constant_instruction("OP_METHOD", chunk, offset);
+        }
+        OpCode::OpInherit => {
+            return simple_instruction("OP_INHERIT", offset);
+        }
+        OpCode::OpGetSuper => {
+            return constant_instruction("OP_GET_SUPER", chunk, offset);
+        }
+        OpCode::OpDefineGlobalLong => {
+            return constant_long_instruction("OP_DEFINE_GLOBAL_LONG", chunk, offset);
+        }
+        OpCode::OpGetGlobalLong => {
+            return constant_long_instruction("OP_GET_GLOBAL_LONG", chunk, offset);
+        }
+        OpCode::OpSetGlobalLong => {
+            return constant_long_instruction("OP_SET_GLOBAL_LONG", chunk, offset);
+        }
+        OpCode::OpClassLong => {
+            return constant_long_instruction("OP_CLASS_LONG", chunk, offset);
+        }
+        OpCode::OpGetPropertyLong => {
+            return constant_long_instruction("OP_GET_PROPERTY_LONG", chunk, offset);
+        }
+        OpCode::OpSetPropertyLong => {
+            return constant_long_instruction("OP_SET_PROPERTY_LONG", chunk, offset);
+        }
+        OpCode::OpMethodLong => {
+            return constant_long_instruction("OP_METHOD_LONG", chunk, offset);
+        }
+        OpCode::OpGetSuperLong => {
+            return constant_long_instruction("OP_GET_SUPER_LONG", chunk, offset);
+        }
+        OpCode::OpClosureLong => {
+            return closure_instruction("OP_CLOSURE_LONG", chunk, offset);
         }
     }
 }
 
-fn simple_instruction(name: &str, offset: &usize, table: &OpCodeTable) -> usize {
+fn simple_instruction(name: &str, offset: &usize) -> usize {
     println!("{}", name);
 
     return *offset + 1;
 }
 
-fn constant_instruction(name: &str, chunk: &Chunk, offset: &usize, table: &OpCodeTable) -> usize {
+fn byte_instruction(name: &str, chunk: &Chunk, offset: &usize) -> usize {
+    let slot = chunk.code[*offset + 1];
+
+    println!("{:<16} {}", name, slot);
+
+    return *offset + 2;
+}
+
+fn jump_instruction(name: &str, sign: i64, chunk: &Chunk, offset: &usize) -> usize {
+    let jump = ((chunk.code[*offset + 1] as u16) << 8) | chunk.code[*offset + 2] as u16;
+
+    println!(
+        "{:<16} {:4} -> {}",
+        name,
+        offset,
+        *offset as i64 + 3 + sign * jump as i64
+    );
+
+    return *offset + 3;
+}
+
+fn constant_instruction(name: &str, chunk: &Chunk, offset: &usize) -> usize {
     let constant = &chunk.code[*offset + 1];
 
     print!("{:<16} {} '", name, constant);
@@ -87,3 +266,57 @@ fn constant_instruction(name: &str, chunk: &Chunk, offset: &usize, table: &OpCod
 
     return *offset + 2;
 }
+
+fn constant_long_instruction(name: &str, chunk: &Chunk, offset: &usize) -> usize {
+    let constant = ((chunk.code[*offset + 1] as usize) << 16)
+        | ((chunk.code[*offset + 2] as usize) << 8)
+        | chunk.code[*offset + 3] as usize;
+
+    print!("{:<16} {} '", name, constant);
+
+    print_value(chunk.constants.values[constant]);
+
+    println!("'");
+
+    return *offset + 4;
+}
+
+fn closure_instruction(name: &str, chunk: &Chunk, offset: &usize) -> usize {
+    let (constant, width) = if chunk.code[*offset] == opcode_to_u8(OpCode::OpClosureLong) {
+        let constant = ((chunk.code[*offset + 1] as usize) << 16)
+            | ((chunk.code[*offset + 2] as usize) << 8)
+            | chunk.code[*offset + 3] as usize;
+
+        (constant, 3)
+    } else {
+        (chunk.code[*offset + 1] as usize, 1)
+    };
+
+    print!("{:<16} {} ", name, constant);
+
+    let value = chunk.constants.values[constant];
+
+    print_value(value);
+
+    println!();
+
+    let mut offset = *offset + 1 + width;
+
+    if let Value::ValObj(function) = value {
+        for _ in 0..object_function(function).upvalue_count {
+            let is_local = chunk.code[offset];
+            let index = chunk.code[offset + 1];
+
+            println!(
+                "{:04}    |                     {} {}",
+                offset,
+                if is_local == 1 { "local" } else { "upvalue" },
+                index
+            );
+
+            offset += 2;
+        }
+    }
+
+    return offset;
+}
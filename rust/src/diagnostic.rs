@@ -0,0 +1,164 @@
+use crate::common::*;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    SeverityError,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub line: usize,
+    pub col: usize,
+    pub start: usize,
+    pub length: usize,
+    pub location: String,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::SeverityError => write!(f, "Error"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[line {}] {}{}: {}",
+            self.line, self.severity, self.location, self.message
+        )
+    }
+}
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+const TAB_WIDTH: usize = 4;
+
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if !color {
+        return text.to_string();
+    }
+
+    return format!("{}{}{}", style, text, RESET);
+}
+
+fn render_report(
+    source: &str,
+    name: &str,
+    message: &str,
+    line: usize,
+    col: usize,
+    length: usize,
+    color: bool,
+) -> String {
+    let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+
+    let gutter = " ".repeat(line.to_string().len());
+
+    let col = col.max(1);
+
+    // Tabs are expanded so the caret lines up with the snippet, and `length`
+    // is a byte span, so it is measured against each character's UTF-8 size.
+    let mut snippet = String::new();
+
+    let mut padding = 0;
+    let mut underline = 0;
+    let mut covered = 0;
+    let mut chars = 0;
+
+    for c in text.chars() {
+        let width = if c == '\t' { TAB_WIDTH } else { 1 };
+
+        if c == '\t' {
+            snippet.push_str(&" ".repeat(TAB_WIDTH));
+        } else {
+            snippet.push(c);
+        }
+
+        if chars < col - 1 {
+            padding += width;
+        } else if covered < length {
+            underline += width;
+            covered += c.len_utf8();
+        }
+
+        chars += 1;
+    }
+
+    padding += (col - 1).saturating_sub(chars);
+
+    let underline = underline.max(1);
+
+    let mut report = String::new();
+
+    report.push_str(&format!(
+        "{}{}\n",
+        paint("error", RED, color),
+        paint(&format!(": {}", message), BOLD, color)
+    ));
+
+    report.push_str(&format!(
+        "{}{} {}:{}:{}\n",
+        gutter,
+        paint("-->", BLUE, color),
+        name,
+        line,
+        col
+    ));
+
+    report.push_str(&format!("{} {}\n", gutter, paint("|", BLUE, color)));
+
+    report.push_str(&format!(
+        "{} {}\n",
+        paint(&format!("{} |", line), BLUE, color),
+        snippet
+    ));
+
+    report.push_str(&format!(
+        "{} {} {}{}",
+        gutter,
+        paint("|", BLUE, color),
+        " ".repeat(padding),
+        paint(&"^".repeat(underline), RED, color)
+    ));
+
+    return report;
+}
+
+pub fn render_diagnostic(source: &str, name: &str, diagnostic: &Diagnostic, color: bool) -> String {
+    return render_report(
+        source,
+        name,
+        &diagnostic.message,
+        diagnostic.line,
+        diagnostic.col,
+        diagnostic.length,
+        color,
+    );
+}
+
+pub fn render_runtime_error(source: &str, name: &str, error: &RuntimeError, color: bool) -> String {
+    let mut report = render_report(
+        source,
+        name,
+        &error.message,
+        error.line as usize,
+        error.col as usize,
+        error.length as usize,
+        color,
+    );
+
+    for frame in error.trace.iter() {
+        report.push_str(&format!("\n{}", frame));
+    }
+
+    return report;
+}
@@ -0,0 +1,18 @@
+#![allow(clippy::needless_return, clippy::enum_variant_names)]
+
+pub mod bytecode;
+mod chunk;
+pub mod common;
+pub mod compiler;
+mod debug;
+pub mod diagnostic;
+mod memory;
+mod object;
+pub mod scanner;
+mod table;
+mod value;
+pub mod verify;
+pub mod vm;
+
+pub use object::NativeFn;
+pub use value::Value;
@@ -1,26 +1,36 @@
-mod chunk;
-mod common;
-mod compiler;
-mod debug;
-mod scanner;
-mod value;
-mod vm;
-
-use chunk::*;
-use common::*;
-use compiler::*;
-use debug::*;
-use scanner::*;
-use value::*;
-use vm::*;
-
-use std::fs::read_to_string;
-use std::io::{self, BufRead, Write};
+#![allow(clippy::needless_return)]
+
+use lox::bytecode::*;
+use lox::common::*;
+use lox::diagnostic::*;
+use lox::vm::*;
+
+use std::fs::{read, read_to_string, write};
+use std::io::{self, BufRead, IsTerminal, Write};
 
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
 
-    println!("{:?}", args);
+    let stress_gc = args.iter().any(|arg| arg == "--stress-gc");
+    let log_gc = args.iter().any(|arg| arg == "--log-gc");
+
+    let color = !args.iter().any(|arg| arg == "--no-color")
+        && io::stdout().is_terminal()
+        && io::stderr().is_terminal();
+
+    let new_vm = || {
+        let mut vm = init_vm();
+
+        vm.stress_gc = stress_gc;
+        vm.log_gc = log_gc;
+
+        return vm;
+    };
+
+    let args = args
+        .into_iter()
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<String>>();
 
     if args.len() == 1 {
         let stdin = io::stdin();
@@ -29,40 +39,114 @@ fn main() {
 
         let _ = io::stdout().flush();
 
+        let mut vm = new_vm();
+
         for line in stdin.lock().lines() {
-            let mut vm = init_vm();
+            let line = line.unwrap();
+
+            let result = vm.interpret(line.clone(), false);
 
-            vm.interpret(line.unwrap(), false);
+            report_errors(&result, Some((&line, "<repl>")), color);
 
             print!("> ");
 
             let _ = io::stdout().flush();
         }
+    } else if args.len() == 5 && args[1] == "compile" && args[3] == "-o" {
+        let source = read_source(&args[2]);
+
+        let mut vm = new_vm();
+
+        let bytes = compile_bytecode(source.clone(), &mut vm).unwrap_or_else(|diagnostics| {
+            for diagnostic in diagnostics.iter() {
+                eprintln!(
+                    "{}",
+                    render_diagnostic(&source, &args[2], diagnostic, color)
+                );
+            }
+
+            std::process::exit(65);
+        });
+
+        if write(&args[4], bytes).is_err() {
+            eprintln!("Could not write file \"{}\".", args[4]);
+
+            std::process::exit(74);
+        }
+    } else if args.len() == 3 && args[1] == "run" {
+        let bytes = read(&args[2]).unwrap_or_else(|_| {
+            eprintln!("Could not open file \"{}\".", args[2]);
+
+            std::process::exit(74);
+        });
+
+        let mut vm = new_vm();
+
+        let result = vm.interpret_bytecode(&bytes).unwrap_or_else(|error| {
+            eprintln!("Could not load \"{}\": {}", args[2], error);
+
+            std::process::exit(65);
+        });
+
+        exit_with(result, None, color);
     } else if args.len() == 2 {
-        let file_name = &args[1];
+        let source = read_source(&args[1]);
 
-        let file_contents =
-            read_to_string(file_name).expect(&format!("Could not open file \"{}\".", file_name));
+        let mut vm = new_vm();
 
-        println!("{}", file_contents);
+        let result = vm.interpret(source.clone(), false);
 
-        let mut vm = init_vm();
+        exit_with(result, Some((&source, &args[1])), color);
+    } else {
+        println!(
+            "Usage: rlox [--stress-gc] [--log-gc] [--no-color] [path | compile <path> -o <out.loxc> | run <path.loxc>]"
+        );
+        std::process::exit(64);
+    }
+}
 
-        let result = vm.interpret(file_contents, false);
+fn read_source(file_name: &str) -> String {
+    return read_to_string(file_name).unwrap_or_else(|_| {
+        eprintln!("Could not open file \"{}\".", file_name);
 
-        match result {
-            InterpretResult::InterpretCompilerError => {
-                std::process::exit(65);
-            }
-            InterpretResult::InterpretRuntimeError => {
-                std::process::exit(70);
+        std::process::exit(74);
+    });
+}
+
+fn report_errors(result: &InterpretResult, source: Option<(&str, &str)>, color: bool) {
+    match result {
+        InterpretResult::InterpretCompilerError(diagnostics) => {
+            for diagnostic in diagnostics.iter() {
+                match source {
+                    Some((source, name)) => {
+                        eprintln!("{}", render_diagnostic(source, name, diagnostic, color))
+                    }
+                    None => eprintln!("{}", diagnostic),
+                }
             }
-            _ => {
-                std::process::exit(0);
+        }
+        InterpretResult::InterpretRuntimeError(error) => match source {
+            Some((source, name)) => {
+                eprintln!("{}", render_runtime_error(source, name, error, color))
             }
+            None => eprintln!("{}", error),
+        },
+        _ => {}
+    }
+}
+
+fn exit_with(result: InterpretResult, source: Option<(&str, &str)>, color: bool) {
+    report_errors(&result, source, color);
+
+    match result {
+        InterpretResult::InterpretCompilerError(_) => {
+            std::process::exit(65);
+        }
+        InterpretResult::InterpretRuntimeError(_) => {
+            std::process::exit(70);
+        }
+        _ => {
+            std::process::exit(0);
         }
-    } else {
-        println!("Usage: rlox [path]");
-        std::process::exit(64);
     }
 }
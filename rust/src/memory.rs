@@ -0,0 +1,241 @@
+use crate::chunk::*;
+use crate::object::*;
+use crate::table::*;
+use crate::value::*;
+use crate::vm::*;
+
+pub const GC_HEAP_GROW_FACTOR: usize = 2;
+pub const GC_INITIAL_THRESHOLD: usize = 1024 * 1024;
+
+pub fn object_size(kind: &ObjKind) -> usize {
+    let payload = match kind {
+        ObjKind::ObjClosure(closure) => {
+            closure.upvalues.capacity() * std::mem::size_of::<*mut Obj>()
+        }
+        ObjKind::ObjClass(klass) => table_size(&klass.methods),
+        ObjKind::ObjFunction(function) => chunk_size(&function.chunk),
+        ObjKind::ObjInstance(instance) => table_size(&instance.fields),
+        ObjKind::ObjString(string) => string.chars.capacity(),
+        _ => 0,
+    };
+
+    return std::mem::size_of::<Obj>() + payload;
+}
+
+/// Charges the VM for memory an object gained after it was allocated, such as
+/// a function's chunk being written or an instance's fields table growing.
+/// `free_object` subtracts the object's size at that point, so every growth
+/// has to be recorded here to keep `bytes_allocated` balanced.
+pub fn track_growth(vm: &mut VirtualMachine, before: usize, after: usize) {
+    vm.bytes_allocated += after.saturating_sub(before);
+}
+
+pub fn object_type(kind: &ObjKind) -> &'static str {
+    match kind {
+        ObjKind::ObjBoundMethod(_) => "bound method",
+        ObjKind::ObjClass(_) => "class",
+        ObjKind::ObjClosure(_) => "closure",
+        ObjKind::ObjFunction(_) => "function",
+        ObjKind::ObjInstance(_) => "instance",
+        ObjKind::ObjNative(_) => "native",
+        ObjKind::ObjString(_) => "string",
+        ObjKind::ObjUpvalue(_) => "upvalue",
+    }
+}
+
+pub fn free_object(vm: &mut VirtualMachine, object: *mut Obj) {
+    let object = unsafe { Box::from_raw(object) };
+
+    if vm.log_gc {
+        println!("{:p} free type {}", object, object_type(&object.kind));
+    }
+
+    vm.bytes_allocated = vm.bytes_allocated.saturating_sub(object_size(&object.kind));
+}
+
+pub fn free_objects(vm: &mut VirtualMachine) {
+    let mut object = vm.objects;
+
+    while !object.is_null() {
+        let next = unsafe { (*object).next };
+
+        free_object(vm, object);
+
+        object = next;
+    }
+
+    vm.objects = std::ptr::null_mut();
+}
+
+pub fn mark_object(vm: &mut VirtualMachine, object: *mut Obj) {
+    if object.is_null() {
+        return;
+    }
+
+    let header = unsafe { &mut *object };
+
+    if header.is_marked {
+        return;
+    }
+
+    if vm.log_gc {
+        print!("{:p} mark ", object);
+
+        print_value(Value::ValObj(object));
+
+        println!();
+    }
+
+    header.is_marked = true;
+
+    vm.gray_stack.push(object);
+}
+
+pub fn mark_value(vm: &mut VirtualMachine, value: Value) {
+    if let Value::ValObj(object) = value {
+        mark_object(vm, object);
+    }
+}
+
+pub fn mark_table(vm: &mut VirtualMachine, table: &Table) {
+    for entry in table.entries.iter() {
+        mark_object(vm, entry.key);
+        mark_value(vm, entry.value);
+    }
+}
+
+fn mark_roots(vm: &mut VirtualMachine) {
+    for i in 0..vm.stack.len() {
+        mark_value(vm, vm.stack[i]);
+    }
+
+    for i in 0..vm.frames.len() {
+        mark_object(vm, vm.frames[i].closure);
+    }
+
+    for i in 0..vm.open_upvalues.len() {
+        mark_object(vm, vm.open_upvalues[i]);
+    }
+
+    for i in 0..vm.globals.entries.len() {
+        let entry = vm.globals.entries[i];
+
+        mark_object(vm, entry.key);
+        mark_value(vm, entry.value);
+    }
+
+    for i in 0..vm.compiler_roots.len() {
+        mark_object(vm, vm.compiler_roots[i]);
+    }
+
+    mark_object(vm, vm.init_string);
+}
+
+fn blacken_object(vm: &mut VirtualMachine, object: *mut Obj) {
+    if vm.log_gc {
+        print!("{:p} blacken ", object);
+
+        print_value(Value::ValObj(object));
+
+        println!();
+    }
+
+    match unsafe { &(*object).kind } {
+        ObjKind::ObjBoundMethod(bound) => {
+            mark_value(vm, bound.receiver);
+            mark_object(vm, bound.method);
+        }
+        ObjKind::ObjClass(klass) => {
+            mark_object(vm, klass.name);
+            mark_table(vm, &klass.methods);
+        }
+        ObjKind::ObjClosure(closure) => {
+            mark_object(vm, closure.function);
+
+            for upvalue in closure.upvalues.iter() {
+                mark_object(vm, *upvalue);
+            }
+        }
+        ObjKind::ObjFunction(function) => {
+            mark_object(vm, function.name);
+
+            for value in function.chunk.constants.values.iter() {
+                mark_value(vm, *value);
+            }
+        }
+        ObjKind::ObjInstance(instance) => {
+            mark_object(vm, instance.klass);
+            mark_table(vm, &instance.fields);
+        }
+        ObjKind::ObjUpvalue(upvalue) => {
+            if let Some(closed) = upvalue.closed {
+                mark_value(vm, closed);
+            }
+        }
+        ObjKind::ObjNative(_) | ObjKind::ObjString(_) => {}
+    }
+}
+
+fn trace_references(vm: &mut VirtualMachine) {
+    while let Some(object) = vm.gray_stack.pop() {
+        blacken_object(vm, object);
+    }
+}
+
+fn sweep(vm: &mut VirtualMachine) {
+    let mut previous: *mut Obj = std::ptr::null_mut();
+    let mut object = vm.objects;
+
+    while !object.is_null() {
+        let header = unsafe { &mut *object };
+
+        if header.is_marked {
+            header.is_marked = false;
+
+            previous = object;
+            object = header.next;
+        } else {
+            let unreached = object;
+
+            object = header.next;
+
+            if previous.is_null() {
+                vm.objects = object;
+            } else {
+                unsafe { (*previous).next = object };
+            }
+
+            free_object(vm, unreached);
+        }
+    }
+}
+
+pub fn collect_garbage(vm: &mut VirtualMachine) {
+    let before = vm.bytes_allocated;
+
+    if vm.log_gc {
+        println!("-- gc begin");
+    }
+
+    mark_roots(vm);
+
+    trace_references(vm);
+
+    table_remove_white(&mut vm.strings);
+
+    sweep(vm);
+
+    vm.next_gc = vm.bytes_allocated * GC_HEAP_GROW_FACTOR;
+
+    if vm.log_gc {
+        println!("-- gc end");
+
+        println!(
+            "   collected {} bytes (from {} to {}) next at {}",
+            before - vm.bytes_allocated,
+            before,
+            vm.bytes_allocated,
+            vm.next_gc
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use crate::chunk::*;
+use crate::memory::*;
+use crate::table::*;
+use crate::value::*;
+use crate::vm::*;
+
+pub struct Obj {
+    pub kind: ObjKind,
+    pub is_marked: bool,
+    pub next: *mut Obj,
+}
+
+pub enum ObjKind {
+    ObjBoundMethod(ObjBoundMethod),
+    ObjClass(ObjClass),
+    ObjClosure(ObjClosure),
+    ObjFunction(ObjFunction),
+    ObjInstance(ObjInstance),
+    ObjNative(ObjNative),
+    ObjString(ObjString),
+    ObjUpvalue(ObjUpvalue),
+}
+
+pub struct ObjFunction {
+    pub arity: usize,
+    pub upvalue_count: usize,
+    pub chunk: Chunk,
+    pub name: *mut Obj,
+}
+
+pub type NativeFn = fn(&mut VirtualMachine, &[Value]) -> Result<Value, String>;
+
+pub struct ObjNative {
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+pub struct ObjClosure {
+    pub function: *mut Obj,
+    pub upvalues: Vec<*mut Obj>,
+}
+
+pub struct ObjUpvalue {
+    pub location: usize,
+    pub closed: Option<Value>,
+}
+
+pub struct ObjString {
+    pub chars: String,
+    pub hash: u32,
+}
+
+pub struct ObjClass {
+    pub name: *mut Obj,
+    pub methods: Table,
+}
+
+pub struct ObjInstance {
+    pub klass: *mut Obj,
+    pub fields: Table,
+}
+
+pub struct ObjBoundMethod {
+    pub receiver: Value,
+    pub method: *mut Obj,
+}
+
+pub fn is_string(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => matches!(unsafe { &(*object).kind }, ObjKind::ObjString(_)),
+        _ => false,
+    }
+}
+
+pub fn is_closure(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => matches!(unsafe { &(*object).kind }, ObjKind::ObjClosure(_)),
+        _ => false,
+    }
+}
+
+pub fn is_native(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => matches!(unsafe { &(*object).kind }, ObjKind::ObjNative(_)),
+        _ => false,
+    }
+}
+
+pub fn is_class(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => matches!(unsafe { &(*object).kind }, ObjKind::ObjClass(_)),
+        _ => false,
+    }
+}
+
+pub fn is_instance(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => matches!(unsafe { &(*object).kind }, ObjKind::ObjInstance(_)),
+        _ => false,
+    }
+}
+
+pub fn is_bound_method(value: Value) -> bool {
+    match value {
+        Value::ValObj(object) => {
+            matches!(unsafe { &(*object).kind }, ObjKind::ObjBoundMethod(_))
+        }
+        _ => false,
+    }
+}
+
+pub fn as_string<'a>(value: Value) -> &'a ObjString {
+    match value {
+        Value::ValObj(object) => object_string(object),
+        _ => panic!("Value is not a string."),
+    }
+}
+
+pub fn object_string<'a>(object: *mut Obj) -> &'a ObjString {
+    match unsafe { &(*object).kind } {
+        ObjKind::ObjString(string) => string,
+        _ => panic!("Object is not a string."),
+    }
+}
+
+pub fn object_function<'a>(object: *mut Obj) -> &'a mut ObjFunction {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjFunction(function) => function,
+        _ => panic!("Object is not a function."),
+    }
+}
+
+pub fn object_closure<'a>(object: *mut Obj) -> &'a mut ObjClosure {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjClosure(closure) => closure,
+        _ => panic!("Object is not a closure."),
+    }
+}
+
+pub fn object_native<'a>(object: *mut Obj) -> &'a mut ObjNative {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjNative(native) => native,
+        _ => panic!("Object is not a native function."),
+    }
+}
+
+pub fn object_upvalue<'a>(object: *mut Obj) -> &'a mut ObjUpvalue {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjUpvalue(upvalue) => upvalue,
+        _ => panic!("Object is not an upvalue."),
+    }
+}
+
+pub fn object_class<'a>(object: *mut Obj) -> &'a mut ObjClass {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjClass(klass) => klass,
+        _ => panic!("Object is not a class."),
+    }
+}
+
+pub fn object_instance<'a>(object: *mut Obj) -> &'a mut ObjInstance {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjInstance(instance) => instance,
+        _ => panic!("Object is not an instance."),
+    }
+}
+
+pub fn object_bound_method<'a>(object: *mut Obj) -> &'a mut ObjBoundMethod {
+    match unsafe { &mut (*object).kind } {
+        ObjKind::ObjBoundMethod(bound) => bound,
+        _ => panic!("Object is not a bound method."),
+    }
+}
+
+pub fn allocate_object(vm: &mut VirtualMachine, kind: ObjKind) -> *mut Obj {
+    let size = object_size(&kind);
+
+    vm.bytes_allocated += size;
+
+    if vm.stress_gc || vm.bytes_allocated > vm.next_gc {
+        collect_garbage(vm);
+    }
+
+    let object = Box::into_raw(Box::new(Obj {
+        kind,
+        is_marked: false,
+        next: vm.objects,
+    }));
+
+    vm.objects = object;
+
+    if vm.log_gc {
+        println!(
+            "{:p} allocate {} for {}",
+            object,
+            size,
+            object_type(unsafe { &(*object).kind })
+        );
+    }
+
+    return object;
+}
+
+pub fn new_function(vm: &mut VirtualMachine) -> *mut Obj {
+    return allocate_object(
+        vm,
+        ObjKind::ObjFunction(ObjFunction {
+            arity: 0,
+            upvalue_count: 0,
+            chunk: init_chunk(),
+            name: std::ptr::null_mut(),
+        }),
+    );
+}
+
+pub fn new_closure(vm: &mut VirtualMachine, function: *mut Obj) -> *mut Obj {
+    let upvalues = Vec::with_capacity(object_function(function).upvalue_count);
+
+    return allocate_object(vm, ObjKind::ObjClosure(ObjClosure { function, upvalues }));
+}
+
+pub fn new_native(vm: &mut VirtualMachine, arity: usize, function: NativeFn) -> *mut Obj {
+    return allocate_object(vm, ObjKind::ObjNative(ObjNative { arity, function }));
+}
+
+pub fn new_upvalue(vm: &mut VirtualMachine, location: usize) -> *mut Obj {
+    return allocate_object(
+        vm,
+        ObjKind::ObjUpvalue(ObjUpvalue {
+            location,
+            closed: None,
+        }),
+    );
+}
+
+pub fn new_class(vm: &mut VirtualMachine, name: *mut Obj) -> *mut Obj {
+    return allocate_object(
+        vm,
+        ObjKind::ObjClass(ObjClass {
+            name,
+            methods: init_table(),
+        }),
+    );
+}
+
+pub fn new_instance(vm: &mut VirtualMachine, klass: *mut Obj) -> *mut Obj {
+    return allocate_object(
+        vm,
+        ObjKind::ObjInstance(ObjInstance {
+            klass,
+            fields: init_table(),
+        }),
+    );
+}
+
+pub fn new_bound_method(vm: &mut VirtualMachine, receiver: Value, method: *mut Obj) -> *mut Obj {
+    return allocate_object(
+        vm,
+        ObjKind::ObjBoundMethod(ObjBoundMethod { receiver, method }),
+    );
+}
+
+fn allocate_string(vm: &mut VirtualMachine, chars: String, hash: u32) -> *mut Obj {
+    let string = allocate_object(vm, ObjKind::ObjString(ObjString { chars, hash }));
+
+    table_set(&mut vm.strings, string, Value::ValNil);
+
+    return string;
+}
+
+pub fn hash_string(chars: &str) -> u32 {
+    let mut hash: u32 = 2166136261;
+
+    for byte in chars.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(16777619);
+    }
+
+    return hash;
+}
+
+pub fn take_string(vm: &mut VirtualMachine, chars: String) -> *mut Obj {
+    let hash = hash_string(&chars);
+
+    if let Some(interned) = table_find_string(&vm.strings, &chars, hash) {
+        return interned;
+    }
+
+    return allocate_string(vm, chars, hash);
+}
+
+pub fn copy_string(vm: &mut VirtualMachine, chars: &str) -> *mut Obj {
+    let hash = hash_string(chars);
+
+    if let Some(interned) = table_find_string(&vm.strings, chars, hash) {
+        return interned;
+    }
+
+    return allocate_string(vm, chars.to_string(), hash);
+}
+
+fn print_function(function: &ObjFunction) {
+    if function.name.is_null() {
+        print!("<script>");
+
+        return;
+    }
+
+    print!("<fn {}>", object_string(function.name).chars);
+}
+
+pub fn print_object(value: Value) {
+    if let Value::ValObj(object) = value {
+        match unsafe { &(*object).kind } {
+            ObjKind::ObjBoundMethod(bound) => {
+                print_function(object_function(object_closure(bound.method).function))
+            }
+            ObjKind::ObjClass(klass) => print!("{}", object_string(klass.name).chars),
+            ObjKind::ObjClosure(closure) => print_function(object_function(closure.function)),
+            ObjKind::ObjFunction(function) => print_function(function),
+            ObjKind::ObjInstance(instance) => {
+                print!(
+                    "{} instance",
+                    object_string(object_class(instance.klass).name).chars
+                )
+            }
+            ObjKind::ObjNative(_) => print!("<native fn>"),
+            ObjKind::ObjString(string) => print!("{}", string.chars),
+            ObjKind::ObjUpvalue(_) => print!("upvalue"),
+        }
+    }
+}
@@ -56,6 +56,7 @@ pub trait TokenScanner {
     fn is_at_end(&mut self) -> bool;
     fn make_token(&mut self, kind: TokenKind) -> Token;
     fn error_token(&mut self, msg: String) -> Token;
+    fn error_message(&self) -> &str;
     fn advance(&mut self) -> char;
     fn match_char(&mut self, expected: char) -> bool;
     fn skip_whitespace(&mut self);
@@ -74,6 +75,9 @@ pub struct Scanner {
     current: usize,
     line: usize,
     col: usize,
+    start_line: usize,
+    start_col: usize,
+    error: String,
 }
 
 impl TokenScanner for Scanner {
@@ -81,6 +85,8 @@ impl TokenScanner for Scanner {
         self.skip_whitespace();
 
         self.start = self.current;
+        self.start_line = self.line;
+        self.start_col = self.col;
 
         if self.is_at_end() {
             return self.make_token(TokenKind::Eof);
@@ -222,26 +228,33 @@ impl TokenScanner for Scanner {
             kind,
             start: self.start,
             length: self.current - self.start,
-            line: self.line,
-            col: self.col,
+            line: self.start_line,
+            col: self.start_col,
         }
     }
 
     fn error_token(&mut self, msg: String) -> Token {
+        self.error = msg;
+
         Token {
             kind: TokenKind::Err,
             start: self.start,
-            length: msg.len(),
-            line: self.line,
-            col: self.col,
+            length: self.current - self.start,
+            line: self.start_line,
+            col: self.start_col,
         }
     }
 
+    /// The message for the most recent `TokenKind::Err` token.
+    fn error_message(&self) -> &str {
+        return &self.error;
+    }
+
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.col += 1;
+        let c = self.peek();
 
-        let c = self.source.chars().nth(self.current - 1).unwrap();
+        self.current += c.len_utf8();
+        self.col += 1;
 
         if c == '\n' {
             self.line += 1;
@@ -260,7 +273,7 @@ impl TokenScanner for Scanner {
             return false;
         }
 
-        self.current += 1;
+        self.advance();
 
         return true;
     }
@@ -282,29 +295,23 @@ impl TokenScanner for Scanner {
     }
 
     fn peek(&mut self) -> char {
-        if self.current >= self.source.len() {
-            return '\0';
-        }
-
-        return self.source.chars().nth(self.current).unwrap();
+        return self.source[self.current..].chars().next().unwrap_or('\0');
     }
 
     fn peek_next(&mut self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
+        let mut chars = self.source[self.current..].chars();
+
+        chars.next();
 
-        return self.source.chars().nth(self.current + 1).unwrap();
+        return chars.next().unwrap_or('\0');
     }
 
     fn is_digit(&mut self, expected: char) -> bool {
-        expected >= '0' && expected <= '9'
+        expected.is_ascii_digit()
     }
 
     fn is_alpha(&mut self, expected: char) -> bool {
-        (expected >= 'a' && expected <= 'z')
-            || (expected >= 'A' && expected <= 'Z')
-            || expected == '_'
+        expected.is_ascii_alphabetic() || expected == '_'
     }
 }
 
@@ -315,5 +322,8 @@ pub fn init_scanner(source: String) -> Scanner {
         current: 0,
         line: 1,
         col: 1,
+        start_line: 1,
+        start_col: 1,
+        error: String::new(),
     }
 }
@@ -0,0 +1,179 @@
+use crate::object::*;
+use crate::value::*;
+
+const TABLE_MAX_LOAD: f64 = 0.75;
+
+#[derive(Clone, Copy)]
+pub struct Entry {
+    pub key: *mut Obj,
+    pub value: Value,
+}
+
+pub struct Table {
+    pub count: usize,
+    pub entries: Vec<Entry>,
+}
+
+pub fn init_table() -> Table {
+    Table {
+        count: 0,
+        entries: Vec::new(),
+    }
+}
+
+pub fn table_size(table: &Table) -> usize {
+    return table.entries.capacity() * std::mem::size_of::<Entry>();
+}
+
+fn find_entry(entries: &[Entry], key: *mut Obj) -> usize {
+    let capacity = entries.len();
+
+    let mut index = object_string(key).hash as usize % capacity;
+    let mut tombstone = None;
+
+    loop {
+        let entry = &entries[index];
+
+        if entry.key.is_null() {
+            if entry.value == Value::ValNil {
+                return tombstone.unwrap_or(index);
+            } else if tombstone.is_none() {
+                tombstone = Some(index);
+            }
+        } else if entry.key == key {
+            return index;
+        }
+
+        index = (index + 1) % capacity;
+    }
+}
+
+fn adjust_capacity(table: &mut Table, capacity: usize) {
+    let mut entries = vec![
+        Entry {
+            key: std::ptr::null_mut(),
+            value: Value::ValNil,
+        };
+        capacity
+    ];
+
+    table.count = 0;
+
+    for entry in table.entries.iter() {
+        if entry.key.is_null() {
+            continue;
+        }
+
+        let index = find_entry(&entries, entry.key);
+
+        entries[index] = *entry;
+
+        table.count += 1;
+    }
+
+    table.entries = entries;
+}
+
+pub fn table_get(table: &Table, key: *mut Obj) -> Option<Value> {
+    if table.count == 0 {
+        return None;
+    }
+
+    let entry = &table.entries[find_entry(&table.entries, key)];
+
+    if entry.key.is_null() {
+        return None;
+    }
+
+    return Some(entry.value);
+}
+
+pub fn table_set(table: &mut Table, key: *mut Obj, value: Value) -> bool {
+    if (table.count + 1) as f64 > table.entries.len() as f64 * TABLE_MAX_LOAD {
+        let capacity = if table.entries.len() < 8 {
+            8
+        } else {
+            table.entries.len() * 2
+        };
+
+        adjust_capacity(table, capacity);
+    }
+
+    let index = find_entry(&table.entries, key);
+    let entry = &mut table.entries[index];
+
+    let is_new_key = entry.key.is_null();
+
+    if is_new_key && entry.value == Value::ValNil {
+        table.count += 1;
+    }
+
+    entry.key = key;
+    entry.value = value;
+
+    return is_new_key;
+}
+
+pub fn table_delete(table: &mut Table, key: *mut Obj) -> bool {
+    if table.count == 0 {
+        return false;
+    }
+
+    let index = find_entry(&table.entries, key);
+    let entry = &mut table.entries[index];
+
+    if entry.key.is_null() {
+        return false;
+    }
+
+    entry.key = std::ptr::null_mut();
+    entry.value = Value::ValBool(true);
+
+    return true;
+}
+
+pub fn table_add_all(from: &Table, to: &mut Table) {
+    for entry in from.entries.iter() {
+        if !entry.key.is_null() {
+            table_set(to, entry.key, entry.value);
+        }
+    }
+}
+
+pub fn table_find_string(table: &Table, chars: &str, hash: u32) -> Option<*mut Obj> {
+    if table.count == 0 {
+        return None;
+    }
+
+    let capacity = table.entries.len();
+
+    let mut index = hash as usize % capacity;
+
+    loop {
+        let entry = &table.entries[index];
+
+        if entry.key.is_null() {
+            if entry.value == Value::ValNil {
+                return None;
+            }
+        } else {
+            let string = object_string(entry.key);
+
+            if string.hash == hash && string.chars == chars {
+                return Some(entry.key);
+            }
+        }
+
+        index = (index + 1) % capacity;
+    }
+}
+
+pub fn table_remove_white(table: &mut Table) {
+    for i in 0..table.entries.len() {
+        let key = table.entries[i].key;
+
+        if !key.is_null() && !unsafe { (*key).is_marked } {
+            table_delete(table, key);
+        }
+    }
+}
@@ -1,4 +1,12 @@
-pub type Value = f64;
+use crate::object::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    ValBool(bool),
+    ValNil,
+    ValNumber(f64),
+    ValObj(*mut Obj),
+}
 
 #[derive(Clone)]
 pub struct ValueArray {
@@ -14,5 +22,42 @@ pub fn write_value_array(array: &mut ValueArray, value: Value) {
 }
 
 pub fn print_value(value: Value) {
-    print!("{}", value);
+    match value {
+        Value::ValBool(boolean) => print!("{}", boolean),
+        Value::ValNil => print!("nil"),
+        Value::ValNumber(number) => print!("{}", number),
+        Value::ValObj(_) => print_object(value),
+    }
+}
+
+pub fn is_falsey(value: Value) -> bool {
+    matches!(value, Value::ValNil | Value::ValBool(false))
+}
+
+pub fn values_equal(a: Value, b: Value) -> bool {
+    match (a, b) {
+        (Value::ValBool(a), Value::ValBool(b)) => a == b,
+        (Value::ValNil, Value::ValNil) => true,
+        (Value::ValNumber(a), Value::ValNumber(b)) => a == b,
+        (Value::ValObj(a), Value::ValObj(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Hashable identity of a value: numbers by bit pattern, objects by address.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValueKey {
+    KeyBool(bool),
+    KeyNil,
+    KeyNumber(u64),
+    KeyObj(*mut Obj),
+}
+
+pub fn value_key(value: Value) -> ValueKey {
+    match value {
+        Value::ValBool(boolean) => ValueKey::KeyBool(boolean),
+        Value::ValNil => ValueKey::KeyNil,
+        Value::ValNumber(number) => ValueKey::KeyNumber(number.to_bits()),
+        Value::ValObj(object) => ValueKey::KeyObj(object),
+    }
 }
@@ -0,0 +1,419 @@
+use crate::chunk::*;
+use crate::common::*;
+use crate::object::*;
+use crate::value::*;
+use std::convert::TryFrom;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerifyErrorKind {
+    VerifyEmptyLineTable,
+    VerifyUnknownOpcode(u8),
+    VerifyTruncatedInstruction,
+    VerifyConstantOutOfRange(usize),
+    VerifyConstantType(usize, &'static str),
+    VerifyJumpOutOfRange(i64),
+    VerifyJumpIntoInstruction(usize),
+    VerifyLocalOutOfRange(u8),
+    VerifyUpvalueOutOfRange(u8),
+    VerifyBadUpvalueFlag(u8),
+    VerifyStackUnderflow,
+    VerifyStackMismatch(usize, usize),
+    VerifyFallsOffEnd,
+    VerifyScriptSignature,
+}
+
+#[derive(Debug, Clone)]
+pub struct VerifyError {
+    pub function: String,
+    pub offset: usize,
+    pub kind: VerifyErrorKind,
+}
+
+impl fmt::Display for VerifyErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VerifyErrorKind::VerifyEmptyLineTable => write!(f, "Missing line information."),
+            VerifyErrorKind::VerifyUnknownOpcode(byte) => write!(f, "Unknown opcode {}.", byte),
+            VerifyErrorKind::VerifyTruncatedInstruction => {
+                write!(f, "Instruction operands run past the end of the chunk.")
+            }
+            VerifyErrorKind::VerifyConstantOutOfRange(index) => {
+                write!(f, "Constant index {} is out of range.", index)
+            }
+            VerifyErrorKind::VerifyConstantType(index, expected) => {
+                write!(f, "Constant {} must be a {}.", index, expected)
+            }
+            VerifyErrorKind::VerifyJumpOutOfRange(target) => {
+                write!(f, "Jump target {} is out of range.", target)
+            }
+            VerifyErrorKind::VerifyJumpIntoInstruction(target) => {
+                write!(f, "Jump target {} is inside an instruction.", target)
+            }
+            VerifyErrorKind::VerifyLocalOutOfRange(slot) => {
+                write!(f, "Local slot {} is out of range.", slot)
+            }
+            VerifyErrorKind::VerifyUpvalueOutOfRange(index) => {
+                write!(f, "Upvalue index {} is out of range.", index)
+            }
+            VerifyErrorKind::VerifyBadUpvalueFlag(flag) => {
+                write!(f, "Upvalue flag must be 0 or 1, got {}.", flag)
+            }
+            VerifyErrorKind::VerifyStackUnderflow => write!(f, "Stack underflow."),
+            VerifyErrorKind::VerifyStackMismatch(expected, found) => write!(
+                f,
+                "Stack depth {} does not match depth {} from another path.",
+                found, expected
+            ),
+            VerifyErrorKind::VerifyFallsOffEnd => {
+                write!(f, "Execution can run past the end of the chunk.")
+            }
+            VerifyErrorKind::VerifyScriptSignature => write!(
+                f,
+                "Top-level script cannot take parameters or capture upvalues."
+            ),
+        }
+    }
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Invalid bytecode in {} at offset {:04}: {}",
+            self.function, self.offset, self.kind
+        )
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Instruction {
+    opcode: OpCode,
+    length: usize,
+}
+
+pub(crate) fn verify_script(function: *mut Obj) -> Result<(), VerifyError> {
+    let script = object_function(function);
+
+    if script.arity != 0 || script.upvalue_count != 0 {
+        return Err(VerifyError {
+            function: function_name(script),
+            offset: 0,
+            kind: VerifyErrorKind::VerifyScriptSignature,
+        });
+    }
+
+    let mut pending: Vec<&ObjFunction> = vec![script];
+
+    while let Some(function) = pending.pop() {
+        verify_function(function)?;
+
+        for value in function.chunk.constants.values.iter() {
+            if let Value::ValObj(object) = *value {
+                if let ObjKind::ObjFunction(nested) = unsafe { &(*object).kind } {
+                    pending.push(nested);
+                }
+            }
+        }
+    }
+
+    return Ok(());
+}
+
+fn function_name(function: &ObjFunction) -> String {
+    if function.name.is_null() {
+        return "script".to_string();
+    }
+
+    return format!("{}()", object_string(function.name).chars);
+}
+
+fn verify_function(function: &ObjFunction) -> Result<(), VerifyError> {
+    let error = |offset: usize, kind: VerifyErrorKind| VerifyError {
+        function: function_name(function),
+        offset,
+        kind,
+    };
+
+    let chunk = &function.chunk;
+
+    if chunk.lines.first().map(|start| start.offset) != Some(0) {
+        return Err(error(0, VerifyErrorKind::VerifyEmptyLineTable));
+    }
+
+    let mut instructions = vec![None; chunk.code.len()];
+
+    let mut offset = 0;
+
+    while offset < chunk.code.len() {
+        let instruction =
+            decode_instruction(function, offset).map_err(|kind| error(offset, kind))?;
+
+        offset += instruction.length;
+
+        instructions[offset - instruction.length] = Some(instruction);
+    }
+
+    let mut depths: Vec<Option<usize>> = vec![None; chunk.code.len()];
+
+    let mut worklist = vec![(0, function.arity + 1)];
+
+    while let Some((offset, depth)) = worklist.pop() {
+        if offset >= chunk.code.len() {
+            return Err(error(offset, VerifyErrorKind::VerifyFallsOffEnd));
+        }
+
+        let instruction = match &instructions[offset] {
+            Some(instruction) => instruction,
+            None => {
+                return Err(error(
+                    offset,
+                    VerifyErrorKind::VerifyJumpIntoInstruction(offset),
+                ))
+            }
+        };
+
+        match depths[offset] {
+            Some(expected) if expected == depth => continue,
+            Some(expected) => {
+                return Err(error(
+                    offset,
+                    VerifyErrorKind::VerifyStackMismatch(expected, depth),
+                ))
+            }
+            None => depths[offset] = Some(depth),
+        }
+
+        let operand = |index: usize| chunk.code[offset + index];
+
+        let (needs, pops, pushes) = match instruction.opcode {
+            OpCode::OpConstant
+            | OpCode::OpConstantLong
+            | OpCode::OpNil
+            | OpCode::OpTrue
+            | OpCode::OpFalse
+            | OpCode::OpGetGlobal
+            | OpCode::OpGetGlobalLong
+            | OpCode::OpGetUpvalue
+            | OpCode::OpClass
+            | OpCode::OpClassLong => (0, 0, 1),
+            OpCode::OpGetLocal | OpCode::OpSetLocal => {
+                if operand(1) as usize >= depth {
+                    return Err(error(
+                        offset,
+                        VerifyErrorKind::VerifyLocalOutOfRange(operand(1)),
+                    ));
+                }
+
+                if instruction.opcode == OpCode::OpGetLocal {
+                    (0, 0, 1)
+                } else {
+                    (1, 0, 0)
+                }
+            }
+            OpCode::OpClosure | OpCode::OpClosureLong => {
+                let first = if is_long_opcode(instruction.opcode) {
+                    4
+                } else {
+                    2
+                };
+
+                for pair in (first..instruction.length).step_by(2) {
+                    if operand(pair) == 1 && operand(pair + 1) as usize >= depth {
+                        return Err(error(
+                            offset,
+                            VerifyErrorKind::VerifyLocalOutOfRange(operand(pair + 1)),
+                        ));
+                    }
+                }
+
+                (0, 0, 1)
+            }
+            OpCode::OpNegate
+            | OpCode::OpNot
+            | OpCode::OpSetGlobal
+            | OpCode::OpSetGlobalLong
+            | OpCode::OpSetUpvalue
+            | OpCode::OpGetProperty
+            | OpCode::OpGetPropertyLong
+            | OpCode::OpJumpIfFalse => (1, 0, 0),
+            OpCode::OpPop
+            | OpCode::OpPrint
+            | OpCode::OpDefineGlobal
+            | OpCode::OpDefineGlobalLong
+            | OpCode::OpCloseUpvalue
+            | OpCode::OpReturn => (1, 1, 0),
+            OpCode::OpAdd
+            | OpCode::OpSubtract
+            | OpCode::OpMultiply
+            | OpCode::OpDivide
+            | OpCode::OpEqual
+            | OpCode::OpGreater
+            | OpCode::OpLess
+            | OpCode::OpSetProperty
+            | OpCode::OpSetPropertyLong
+            | OpCode::OpMethod
+            | OpCode::OpMethodLong
+            | OpCode::OpInherit
+            | OpCode::OpGetSuper
+            | OpCode::OpGetSuperLong => (2, 1, 0),
+            OpCode::OpCall => {
+                let arg_count = operand(1) as usize;
+
+                (arg_count + 1, arg_count, 0)
+            }
+            OpCode::OpJump | OpCode::OpLoop => (0, 0, 0),
+        };
+
+        if depth < needs {
+            return Err(error(offset, VerifyErrorKind::VerifyStackUnderflow));
+        }
+
+        let depth = depth - pops + pushes;
+
+        let next = offset + instruction.length;
+
+        let jump = || ((operand(1) as usize) << 8) | operand(2) as usize;
+
+        match instruction.opcode {
+            OpCode::OpReturn => {}
+            OpCode::OpJump => worklist.push((next + jump(), depth)),
+            OpCode::OpJumpIfFalse => {
+                worklist.push((next, depth));
+                worklist.push((next + jump(), depth));
+            }
+            OpCode::OpLoop => {
+                if jump() > next {
+                    let target = next as i64 - jump() as i64;
+
+                    return Err(error(offset, VerifyErrorKind::VerifyJumpOutOfRange(target)));
+                }
+
+                worklist.push((next - jump(), depth));
+            }
+            _ => worklist.push((next, depth)),
+        }
+    }
+
+    return Ok(());
+}
+
+fn decode_instruction(
+    function: &ObjFunction,
+    offset: usize,
+) -> Result<Instruction, VerifyErrorKind> {
+    let chunk = &function.chunk;
+
+    let opcode =
+        OpCode::try_from(chunk.code[offset]).map_err(VerifyErrorKind::VerifyUnknownOpcode)?;
+
+    let mut length = match opcode {
+        _ if is_long_opcode(opcode) => 4,
+        _ if long_opcode(opcode).is_some() => 2,
+        OpCode::OpJump | OpCode::OpJumpIfFalse | OpCode::OpLoop => 3,
+        OpCode::OpGetLocal
+        | OpCode::OpSetLocal
+        | OpCode::OpGetUpvalue
+        | OpCode::OpSetUpvalue
+        | OpCode::OpCall => 2,
+        _ => 1,
+    };
+
+    if offset + length > chunk.code.len() {
+        return Err(VerifyErrorKind::VerifyTruncatedInstruction);
+    }
+
+    let operand = chunk.code.get(offset + 1).copied().unwrap_or(0);
+
+    let index = if is_long_opcode(opcode) {
+        ((operand as usize) << 16)
+            | ((chunk.code[offset + 2] as usize) << 8)
+            | chunk.code[offset + 3] as usize
+    } else {
+        operand as usize
+    };
+
+    match opcode {
+        OpCode::OpConstant | OpCode::OpConstantLong => {
+            constant(chunk, index)?;
+        }
+        OpCode::OpDefineGlobal
+        | OpCode::OpDefineGlobalLong
+        | OpCode::OpGetGlobal
+        | OpCode::OpGetGlobalLong
+        | OpCode::OpSetGlobal
+        | OpCode::OpSetGlobalLong
+        | OpCode::OpClass
+        | OpCode::OpClassLong
+        | OpCode::OpGetProperty
+        | OpCode::OpGetPropertyLong
+        | OpCode::OpSetProperty
+        | OpCode::OpSetPropertyLong
+        | OpCode::OpMethod
+        | OpCode::OpMethodLong
+        | OpCode::OpGetSuper
+        | OpCode::OpGetSuperLong => {
+            let name = constant(chunk, index)?;
+
+            if !is_string(name) {
+                return Err(VerifyErrorKind::VerifyConstantType(index, "string"));
+            }
+        }
+        OpCode::OpGetUpvalue | OpCode::OpSetUpvalue
+            if operand as usize >= function.upvalue_count =>
+        {
+            return Err(VerifyErrorKind::VerifyUpvalueOutOfRange(operand));
+        }
+        OpCode::OpJump | OpCode::OpJumpIfFalse => {
+            let jump = ((operand as usize) << 8) | chunk.code[offset + 2] as usize;
+
+            let target = offset + length + jump;
+
+            if target > chunk.code.len() {
+                return Err(VerifyErrorKind::VerifyJumpOutOfRange(target as i64));
+            }
+        }
+        OpCode::OpClosure | OpCode::OpClosureLong => {
+            let nested = match constant(chunk, index)? {
+                Value::ValObj(object) => match unsafe { &(*object).kind } {
+                    ObjKind::ObjFunction(nested) => nested,
+                    _ => return Err(VerifyErrorKind::VerifyConstantType(index, "function")),
+                },
+                _ => return Err(VerifyErrorKind::VerifyConstantType(index, "function")),
+            };
+
+            let upvalues = offset + length;
+
+            length += nested.upvalue_count * 2;
+
+            if offset + length > chunk.code.len() {
+                return Err(VerifyErrorKind::VerifyTruncatedInstruction);
+            }
+
+            for pair in (upvalues..offset + length).step_by(2) {
+                let is_local = chunk.code[pair];
+                let index = chunk.code[pair + 1];
+
+                if is_local > 1 {
+                    return Err(VerifyErrorKind::VerifyBadUpvalueFlag(is_local));
+                }
+
+                if is_local == 0 && index as usize >= function.upvalue_count {
+                    return Err(VerifyErrorKind::VerifyUpvalueOutOfRange(index));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    return Ok(Instruction { opcode, length });
+}
+
+fn constant(chunk: &Chunk, index: usize) -> Result<Value, VerifyErrorKind> {
+    return chunk
+        .constants
+        .values
+        .get(index)
+        .copied()
+        .ok_or(VerifyErrorKind::VerifyConstantOutOfRange(index));
+}
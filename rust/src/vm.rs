@@ -1,30 +1,136 @@
-use crate::chunk::*;
+use crate::bytecode::*;
 use crate::common::*;
 use crate::compiler::*;
 use crate::debug::*;
+use crate::memory::*;
+use crate::object::*;
+use crate::table::*;
 use crate::value::*;
+use crate::verify::*;
+use std::convert::TryFrom;
 
 pub trait Interpreter {
     fn interpret(&mut self, source: String, disassemble: bool) -> InterpretResult;
+    fn interpret_bytecode(&mut self, bytes: &[u8]) -> Result<InterpretResult, LoadError>;
+    fn define_native(&mut self, name: &str, arity: usize, function: NativeFn);
+}
+
+pub(crate) trait Machine {
+    fn interpret_function(&mut self, function: *mut Obj, disassemble: bool) -> InterpretResult;
     fn run(&mut self, disassemble: bool) -> InterpretResult;
+    fn call(&mut self, closure: *mut Obj, arg_count: usize) -> Result<(), InterpretResult>;
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), InterpretResult>;
+    fn bind_method(&mut self, klass: *mut Obj, name: *mut Obj) -> Result<(), InterpretResult>;
+    fn capture_upvalue(&mut self, location: usize) -> *mut Obj;
+    fn close_upvalues(&mut self, last: usize);
+    fn frame(&mut self) -> &mut CallFrame;
+    fn read_byte(&mut self) -> u8;
+    fn read_short(&mut self) -> u16;
+    fn read_constant(&mut self) -> Value;
+    fn read_constant_long(&mut self) -> Value;
+    fn read_constant_operand(&mut self, instruction: OpCode) -> Value;
+    fn reset_stack(&mut self);
+    fn runtime_error(&mut self, message: &str) -> InterpretResult;
 }
 
-pub struct VirtualMachine {
-    chunk: Chunk,
+const FRAMES_MAX: usize = 64;
+const STACK_MAX: usize = FRAMES_MAX * UINT8_COUNT;
+
+pub(crate) struct CallFrame {
+    pub(crate) closure: *mut Obj,
+    function: *mut Obj,
     ip: usize,
-    stack: Vec<Value>,
+    slots: usize,
+}
+
+pub struct VirtualMachine {
+    pub(crate) frames: Vec<CallFrame>,
+    pub(crate) stack: Vec<Value>,
+    pub(crate) open_upvalues: Vec<*mut Obj>,
+    pub(crate) objects: *mut Obj,
+    pub(crate) strings: Table,
+    pub(crate) globals: Table,
+    pub(crate) init_string: *mut Obj,
+    pub(crate) compiler_roots: Vec<*mut Obj>,
+    pub(crate) gray_stack: Vec<*mut Obj>,
+    pub(crate) bytes_allocated: usize,
+    pub(crate) next_gc: usize,
+    pub stress_gc: bool,
+    pub log_gc: bool,
+}
+
+macro_rules! binary_op {
+    ($vm:expr, $kind:path, $op:tt) => {{
+        if $vm.stack.len() < 2 {
+            return $vm.runtime_error("Stack underflow.");
+        }
+
+        let right = $vm.stack.pop().unwrap();
+        let left = $vm.stack.pop().unwrap();
+
+        match (left, right) {
+            (Value::ValNumber(left), Value::ValNumber(right)) => {
+                $vm.stack.push($kind(left $op right));
+            }
+            _ => return $vm.runtime_error("Operands must be numbers."),
+        }
+    }};
 }
 
 impl Interpreter for VirtualMachine {
     fn interpret(&mut self, source: String, disassemble: bool) -> InterpretResult {
-        let chunk = init_chunk();
+        let mut compiler = init_compiler(source, self);
+
+        let result = compiler.compile();
+
+        if !result.success {
+            return InterpretResult::InterpretCompilerError(result.diagnostics);
+        }
+
+        return self.interpret_function(result.function, disassemble);
+    }
+
+    fn interpret_bytecode(&mut self, bytes: &[u8]) -> Result<InterpretResult, LoadError> {
+        let function = read_bytecode(self, bytes).map_err(LoadError::LoadFormat)?;
+
+        verify_script(function).map_err(LoadError::LoadVerify)?;
+
+        return Ok(self.interpret_function(function, false));
+    }
+
+    fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let name = copy_string(self, name);
+
+        self.stack.push(Value::ValObj(name));
+
+        let native = new_native(self, arity, function);
+
+        self.stack.push(Value::ValObj(native));
+
+        table_set(&mut self.globals, name, Value::ValObj(native));
+
+        self.stack.pop();
+        self.stack.pop();
+    }
+}
+
+impl Machine for VirtualMachine {
+    fn interpret_function(&mut self, function: *mut Obj, disassemble: bool) -> InterpretResult {
+        if disassemble {
+            disassemble_chunk(&object_function(function).chunk, "<script>");
+        }
 
-        let mut compiler = init_compiler(source);
+        self.stack.push(Value::ValObj(function));
 
-        let result = compiler.compile(chunk);
+        let closure = new_closure(self, function);
 
-        self.chunk = result.chunk;
-        self.ip = 0;
+        self.stack.pop();
+
+        self.stack.push(Value::ValObj(closure));
+
+        if let Err(error) = self.call(closure, 0) {
+            return error;
+        }
 
         let result = self.run(disassemble);
 
@@ -32,95 +138,681 @@ impl Interpreter for VirtualMachine {
     }
 
     fn run(&mut self, disassemble: bool) -> InterpretResult {
-        let table = op_code_table();
-
         loop {
-            let instruction =
-                u8_to_opcode(self.chunk.code[self.ip]).expect("Cannot convert u8 to OpCode.");
-
             if disassemble {
-                disassemble_instruction(&self.chunk, &mut self.ip, &table);
+                let frame = self.frame();
+
+                let mut offset = frame.ip;
+
+                let chunk = &object_function(frame.function).chunk;
+
+                let position = SourcePosition {
+                    line: chunk.get_line(offset),
+                    col: chunk.get_col(offset),
+                };
+
+                disassemble_instruction(chunk, &mut offset, position, None, TRACE_POSITION_WIDTH);
             }
 
+            let instruction = match OpCode::try_from(self.read_byte()) {
+                Ok(instruction) => instruction,
+                Err(byte) => return self.runtime_error(&format!("Unknown opcode {}.", byte)),
+            };
+
             match instruction {
                 OpCode::OpConstant => {
-                    let constant =
-                        self.chunk.constants.values[self.chunk.code[self.ip + 1] as usize];
+                    let constant = self.read_constant();
 
                     self.stack.push(constant);
+                }
+                OpCode::OpConstantLong => {
+                    let constant = self.read_constant_long();
 
-                    self.ip += 1;
+                    self.stack.push(constant);
+                }
+                OpCode::OpNil => {
+                    self.stack.push(Value::ValNil);
+                }
+                OpCode::OpTrue => {
+                    self.stack.push(Value::ValBool(true));
+                }
+                OpCode::OpFalse => {
+                    self.stack.push(Value::ValBool(false));
                 }
                 OpCode::OpNegate => {
-                    if self.stack.len() < 1 {
-                        return InterpretResult::InterpretRuntimeError;
-                    }
+                    let value = match self.stack.pop() {
+                        Some(Value::ValNumber(number)) => -number,
+                        Some(_) => return self.runtime_error("Operand must be a number."),
+                        None => return self.runtime_error("Stack underflow."),
+                    };
 
-                    let value = -self.stack.pop().unwrap();
+                    self.stack.push(Value::ValNumber(value));
+                }
+                OpCode::OpNot => {
+                    let value = match self.stack.pop() {
+                        Some(value) => is_falsey(value),
+                        None => return self.runtime_error("Stack underflow."),
+                    };
 
-                    self.stack.push(value);
+                    self.stack.push(Value::ValBool(value));
                 }
-                OpCode::OpAdd => {
+                OpCode::OpEqual => {
                     if self.stack.len() < 2 {
-                        return InterpretResult::InterpretRuntimeError;
+                        return self.runtime_error("Stack underflow.");
                     }
 
                     let right = self.stack.pop().unwrap();
                     let left = self.stack.pop().unwrap();
 
-                    self.stack.push(left + right);
+                    self.stack.push(Value::ValBool(values_equal(left, right)));
                 }
-                OpCode::OpSubtract => {
+                OpCode::OpGreater => binary_op!(self, Value::ValBool, >),
+                OpCode::OpLess => binary_op!(self, Value::ValBool, <),
+                OpCode::OpAdd => {
                     if self.stack.len() < 2 {
-                        return InterpretResult::InterpretRuntimeError;
+                        return self.runtime_error("Stack underflow.");
                     }
 
                     let right = self.stack.pop().unwrap();
                     let left = self.stack.pop().unwrap();
 
-                    self.stack.push(left - right);
+                    match (left, right) {
+                        (Value::ValNumber(left), Value::ValNumber(right)) => {
+                            self.stack.push(Value::ValNumber(left + right));
+                        }
+                        _ if is_string(left) && is_string(right) => {
+                            let chars = as_string(left).chars.clone() + &as_string(right).chars;
+
+                            let result = take_string(self, chars);
+
+                            self.stack.push(Value::ValObj(result));
+                        }
+                        _ => {
+                            return self
+                                .runtime_error("Operands must be two numbers or two strings.");
+                        }
+                    }
                 }
-                OpCode::OpMultiply => {
-                    if self.stack.len() < 2 {
-                        return InterpretResult::InterpretRuntimeError;
+                OpCode::OpSubtract => binary_op!(self, Value::ValNumber, -),
+                OpCode::OpMultiply => binary_op!(self, Value::ValNumber, *),
+                OpCode::OpDivide => binary_op!(self, Value::ValNumber, /),
+                OpCode::OpPrint => {
+                    let value = match self.stack.pop() {
+                        Some(value) => value,
+                        None => return self.runtime_error("Stack underflow."),
+                    };
+
+                    print_value(value);
+
+                    println!();
+                }
+                OpCode::OpPop => {
+                    if self.stack.pop().is_none() {
+                        return self.runtime_error("Stack underflow.");
                     }
+                }
+                OpCode::OpGetLocal => {
+                    let slot = self.read_byte() as usize + self.frame().slots;
 
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
+                    self.stack.push(self.stack[slot]);
+                }
+                OpCode::OpSetLocal => {
+                    let slot = self.read_byte() as usize + self.frame().slots;
 
-                    self.stack.push(left * right);
+                    self.stack[slot] = *self.stack.last().unwrap();
                 }
-                OpCode::OpDivide => {
-                    if self.stack.len() < 2 {
-                        return InterpretResult::InterpretRuntimeError;
+                OpCode::OpDefineGlobal | OpCode::OpDefineGlobalLong => {
+                    let name = self.read_constant_operand(instruction);
+
+                    let value = match self.stack.pop() {
+                        Some(value) => value,
+                        None => return self.runtime_error("Stack underflow."),
+                    };
+
+                    if let Value::ValObj(name) = name {
+                        table_set(&mut self.globals, name, value);
                     }
+                }
+                OpCode::OpGetGlobal | OpCode::OpGetGlobalLong => {
+                    let name = self.read_constant_operand(instruction);
 
-                    let right = self.stack.pop().unwrap();
-                    let left = self.stack.pop().unwrap();
+                    let value = match name {
+                        Value::ValObj(name) => table_get(&self.globals, name),
+                        _ => None,
+                    };
 
-                    self.stack.push(left / right);
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => {
+                            let message =
+                                format!("Undefined variable '{}'.", as_string(name).chars);
+
+                            return self.runtime_error(&message);
+                        }
+                    }
+                }
+                OpCode::OpSetGlobal | OpCode::OpSetGlobalLong => {
+                    let name = self.read_constant_operand(instruction);
+
+                    let value = match self.stack.last() {
+                        Some(value) => *value,
+                        None => return self.runtime_error("Stack underflow."),
+                    };
+
+                    if let Value::ValObj(key) = name {
+                        if table_set(&mut self.globals, key, value) {
+                            table_delete(&mut self.globals, key);
+
+                            let message =
+                                format!("Undefined variable '{}'.", as_string(name).chars);
+
+                            return self.runtime_error(&message);
+                        }
+                    }
+                }
+                OpCode::OpJump => {
+                    let offset = self.read_short() as usize;
+
+                    self.frame().ip += offset;
+                }
+                OpCode::OpJumpIfFalse => {
+                    let offset = self.read_short() as usize;
+
+                    if is_falsey(*self.stack.last().unwrap()) {
+                        self.frame().ip += offset;
+                    }
+                }
+                OpCode::OpLoop => {
+                    let offset = self.read_short() as usize;
+
+                    self.frame().ip -= offset;
+                }
+                OpCode::OpCall => {
+                    let arg_count = self.read_byte() as usize;
+
+                    let callee = self.stack[self.stack.len() - 1 - arg_count];
+
+                    if let Err(error) = self.call_value(callee, arg_count) {
+                        return error;
+                    }
+                }
+                OpCode::OpClosure | OpCode::OpClosureLong => {
+                    let function = match self.read_constant_operand(instruction) {
+                        Value::ValObj(function) => function,
+                        _ => return self.runtime_error("Expected function constant."),
+                    };
+
+                    let closure = new_closure(self, function);
+
+                    self.stack.push(Value::ValObj(closure));
+
+                    for _ in 0..object_function(function).upvalue_count {
+                        let is_local = self.read_byte() == 1;
+                        let index = self.read_byte() as usize;
+
+                        let upvalue = if is_local {
+                            let location = self.frame().slots + index;
+
+                            self.capture_upvalue(location)
+                        } else {
+                            object_closure(self.frame().closure).upvalues[index]
+                        };
+
+                        object_closure(closure).upvalues.push(upvalue);
+                    }
+                }
+                OpCode::OpGetUpvalue => {
+                    let slot = self.read_byte() as usize;
+
+                    let upvalue =
+                        object_upvalue(object_closure(self.frame().closure).upvalues[slot]);
+
+                    let value = match upvalue.closed {
+                        Some(value) => value,
+                        None => self.stack[upvalue.location],
+                    };
+
+                    self.stack.push(value);
+                }
+                OpCode::OpSetUpvalue => {
+                    let slot = self.read_byte() as usize;
+
+                    let upvalue =
+                        object_upvalue(object_closure(self.frame().closure).upvalues[slot]);
+
+                    let value = *self.stack.last().unwrap();
+
+                    match upvalue.closed {
+                        Some(_) => upvalue.closed = Some(value),
+                        None => self.stack[upvalue.location] = value,
+                    }
+                }
+                OpCode::OpCloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+
+                    self.stack.pop();
+                }
+                OpCode::OpGetProperty | OpCode::OpGetPropertyLong => {
+                    let instance = match *self.stack.last().unwrap() {
+                        Value::ValObj(instance) if is_instance(Value::ValObj(instance)) => instance,
+                        _ => return self.runtime_error("Only instances have properties."),
+                    };
+
+                    let name = match self.read_constant_operand(instruction) {
+                        Value::ValObj(name) => name,
+                        _ => return self.runtime_error("Expected property name constant."),
+                    };
+
+                    if let Some(value) = table_get(&object_instance(instance).fields, name) {
+                        self.stack.pop();
+                        self.stack.push(value);
+
+                        continue;
+                    }
+
+                    if let Err(error) = self.bind_method(object_instance(instance).klass, name) {
+                        return error;
+                    }
+                }
+                OpCode::OpSetProperty | OpCode::OpSetPropertyLong => {
+                    let instance = match self.stack[self.stack.len() - 2] {
+                        Value::ValObj(instance) if is_instance(Value::ValObj(instance)) => instance,
+                        _ => return self.runtime_error("Only instances have fields."),
+                    };
+
+                    let name = match self.read_constant_operand(instruction) {
+                        Value::ValObj(name) => name,
+                        _ => return self.runtime_error("Expected property name constant."),
+                    };
+
+                    let value = self.stack.pop().unwrap();
+
+                    let fields = &mut object_instance(instance).fields;
+
+                    let before = table_size(fields);
+
+                    table_set(fields, name, value);
+
+                    track_growth(self, before, table_size(fields));
+
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::OpClass | OpCode::OpClassLong => {
+                    let name = match self.read_constant_operand(instruction) {
+                        Value::ValObj(name) => name,
+                        _ => return self.runtime_error("Expected class name constant."),
+                    };
+
+                    let klass = new_class(self, name);
+
+                    self.stack.push(Value::ValObj(klass));
+                }
+                OpCode::OpMethod | OpCode::OpMethodLong => {
+                    let name = match self.read_constant_operand(instruction) {
+                        Value::ValObj(name) => name,
+                        _ => return self.runtime_error("Expected method name constant."),
+                    };
+
+                    let method = *self.stack.last().unwrap();
+
+                    if !is_closure(method) {
+                        return self.runtime_error("Methods must be functions.");
+                    }
+
+                    match self.stack[self.stack.len() - 2] {
+                        Value::ValObj(klass) if is_class(Value::ValObj(klass)) => {
+                            let methods = &mut object_class(klass).methods;
+
+                            let before = table_size(methods);
+
+                            table_set(methods, name, method);
+
+                            track_growth(self, before, table_size(methods));
+                        }
+                        _ => return self.runtime_error("Only classes have methods."),
+                    }
+
+                    self.stack.pop();
+                }
+                OpCode::OpInherit => {
+                    let superclass = match self.stack[self.stack.len() - 2] {
+                        Value::ValObj(superclass) if is_class(Value::ValObj(superclass)) => {
+                            superclass
+                        }
+                        _ => return self.runtime_error("Superclass must be a class."),
+                    };
+
+                    match *self.stack.last().unwrap() {
+                        Value::ValObj(subclass) if is_class(Value::ValObj(subclass)) => {
+                            let methods = &mut object_class(subclass).methods;
+
+                            let before = table_size(methods);
+
+                            table_add_all(&object_class(superclass).methods, methods);
+
+                            track_growth(self, before, table_size(methods));
+                        }
+                        _ => return self.runtime_error("Only classes can inherit."),
+                    }
+
+                    self.stack.pop();
+                }
+                OpCode::OpGetSuper | OpCode::OpGetSuperLong => {
+                    let name = match self.read_constant_operand(instruction) {
+                        Value::ValObj(name) => name,
+                        _ => return self.runtime_error("Expected method name constant."),
+                    };
+
+                    let superclass = match self.stack.pop() {
+                        Some(Value::ValObj(superclass)) if is_class(Value::ValObj(superclass)) => {
+                            superclass
+                        }
+                        _ => return self.runtime_error("Superclass must be a class."),
+                    };
+
+                    if let Err(error) = self.bind_method(superclass, name) {
+                        return error;
+                    }
                 }
                 OpCode::OpReturn => {
-                    print_value(self.stack.pop().unwrap_or(0.0));
+                    let result = match self.stack.pop() {
+                        Some(value) => value,
+                        None => return self.runtime_error("Stack underflow."),
+                    };
 
-                    println!();
+                    let frame = self.frames.pop().unwrap();
+
+                    self.close_upvalues(frame.slots);
+
+                    if self.frames.is_empty() {
+                        self.stack.pop();
+
+                        return InterpretResult::InterpretOk;
+                    }
+
+                    self.stack.truncate(frame.slots);
 
-                    return InterpretResult::InterpretOk;
+                    self.stack.push(result);
                 }
-                _ => {
-                    println!("{:?}", instruction);
+            }
+        }
+    }
+
+    fn call(&mut self, closure: *mut Obj, arg_count: usize) -> Result<(), InterpretResult> {
+        let function = object_closure(closure).function;
+
+        let arity = object_function(function).arity;
+
+        if arg_count != arity {
+            let message = format!("Expected {} arguments but got {}.", arity, arg_count);
+
+            return Err(self.runtime_error(&message));
+        }
+
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.runtime_error("Stack overflow."));
+        }
+
+        self.frames.push(CallFrame {
+            closure,
+            function,
+            ip: 0,
+            slots: self.stack.len() - arg_count - 1,
+        });
+
+        return Ok(());
+    }
+
+    fn call_value(&mut self, callee: Value, arg_count: usize) -> Result<(), InterpretResult> {
+        if let Value::ValObj(object) = callee {
+            let callee_slot = self.stack.len() - 1 - arg_count;
+
+            if is_bound_method(callee) {
+                let bound = object_bound_method(object);
+
+                self.stack[callee_slot] = bound.receiver;
+
+                return self.call(bound.method, arg_count);
+            }
+
+            if is_class(callee) {
+                let instance = new_instance(self, object);
+
+                self.stack[callee_slot] = Value::ValObj(instance);
+
+                if let Some(Value::ValObj(initializer)) =
+                    table_get(&object_class(object).methods, self.init_string)
+                {
+                    return self.call(initializer, arg_count);
+                }
+
+                if arg_count != 0 {
+                    let message = format!("Expected 0 arguments but got {}.", arg_count);
+
+                    return Err(self.runtime_error(&message));
                 }
+
+                return Ok(());
+            }
+
+            if is_closure(callee) {
+                return self.call(object, arg_count);
+            }
+
+            if is_native(callee) {
+                let native = object_native(object);
+
+                if arg_count != native.arity {
+                    let message =
+                        format!("Expected {} arguments but got {}.", native.arity, arg_count);
+
+                    return Err(self.runtime_error(&message));
+                }
+
+                let args = self.stack[callee_slot + 1..].to_vec();
+
+                return match (native.function)(self, &args) {
+                    Ok(result) => {
+                        self.stack.truncate(callee_slot);
+                        self.stack.push(result);
+
+                        Ok(())
+                    }
+                    Err(message) => Err(self.runtime_error(&message)),
+                };
+            }
+        }
+
+        return Err(self.runtime_error("Can only call functions and classes."));
+    }
+
+    fn bind_method(&mut self, klass: *mut Obj, name: *mut Obj) -> Result<(), InterpretResult> {
+        let method = match table_get(&object_class(klass).methods, name) {
+            Some(Value::ValObj(method)) => method,
+            _ => {
+                let message = format!("Undefined property '{}'.", object_string(name).chars);
+
+                return Err(self.runtime_error(&message));
             }
+        };
+
+        let receiver = *self.stack.last().unwrap();
+
+        let bound = new_bound_method(self, receiver, method);
+
+        self.stack.pop();
+        self.stack.push(Value::ValObj(bound));
+
+        return Ok(());
+    }
+
+    fn capture_upvalue(&mut self, location: usize) -> *mut Obj {
+        let index = self
+            .open_upvalues
+            .iter()
+            .rposition(|upvalue| object_upvalue(*upvalue).location <= location);
+
+        if let Some(index) = index {
+            let upvalue = self.open_upvalues[index];
 
-            self.ip += 1;
+            if object_upvalue(upvalue).location == location {
+                return upvalue;
+            }
         }
+
+        let created = new_upvalue(self, location);
+
+        let insert_at = index.map_or(0, |index| index + 1);
+
+        self.open_upvalues.insert(insert_at, created);
+
+        return created;
+    }
+
+    fn close_upvalues(&mut self, last: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let upvalue = object_upvalue(*upvalue);
+
+            if upvalue.location < last {
+                break;
+            }
+
+            upvalue.closed = Some(self.stack[upvalue.location]);
+
+            self.open_upvalues.pop();
+        }
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        return self.frames.last_mut().unwrap();
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let frame = self.frame();
+
+        frame.ip += 1;
+
+        return object_function(frame.function).chunk.code[frame.ip - 1];
+    }
+
+    fn read_short(&mut self) -> u16 {
+        let high = self.read_byte() as u16;
+        let low = self.read_byte() as u16;
+
+        return (high << 8) | low;
+    }
+
+    fn read_constant(&mut self) -> Value {
+        let index = self.read_byte() as usize;
+
+        return object_function(self.frame().function)
+            .chunk
+            .constants
+            .values[index];
+    }
+
+    fn read_constant_long(&mut self) -> Value {
+        let high = self.read_byte() as usize;
+        let middle = self.read_byte() as usize;
+        let low = self.read_byte() as usize;
+
+        let index = (high << 16) | (middle << 8) | low;
+
+        return object_function(self.frame().function)
+            .chunk
+            .constants
+            .values[index];
+    }
+
+    fn read_constant_operand(&mut self, instruction: OpCode) -> Value {
+        if is_long_opcode(instruction) {
+            return self.read_constant_long();
+        }
+
+        return self.read_constant();
+    }
+
+    fn reset_stack(&mut self) {
+        self.close_upvalues(0);
+
+        self.stack.clear();
+        self.frames.clear();
+        self.open_upvalues.clear();
+    }
+
+    fn runtime_error(&mut self, message: &str) -> InterpretResult {
+        let frame = self.frame();
+
+        let chunk = &object_function(frame.function).chunk;
+
+        let line = chunk.get_line(frame.ip - 1);
+        let col = chunk.get_col(frame.ip - 1);
+        let length = chunk.get_length(frame.ip - 1);
+
+        let mut trace = Vec::with_capacity(self.frames.len());
+
+        for frame in self.frames.iter().rev() {
+            let function = object_function(frame.function);
+
+            let name = if function.name.is_null() {
+                None
+            } else {
+                Some(object_string(function.name).chars.clone())
+            };
+
+            trace.push(TraceFrame {
+                function: name,
+                line: function.chunk.get_line(frame.ip - 1),
+            });
+        }
+
+        self.reset_stack();
+
+        InterpretResult::InterpretRuntimeError(RuntimeError {
+            message: message.to_string(),
+            line,
+            col,
+            length,
+            trace,
+        })
     }
 }
 
-pub fn init_vm() -> VirtualMachine {
-    VirtualMachine {
-        chunk: init_chunk(),
-        ip: 0,
-        stack: vec![],
+impl Drop for VirtualMachine {
+    fn drop(&mut self) {
+        free_objects(self);
     }
 }
+
+fn clock_native(_vm: &mut VirtualMachine, _args: &[Value]) -> Result<Value, String> {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|error| error.to_string())?;
+
+    return Ok(Value::ValNumber(now.as_secs_f64()));
+}
+
+pub fn init_vm() -> VirtualMachine {
+    let mut vm = VirtualMachine {
+        frames: Vec::with_capacity(FRAMES_MAX),
+        stack: Vec::with_capacity(STACK_MAX),
+        open_upvalues: Vec::new(),
+        objects: std::ptr::null_mut(),
+        strings: init_table(),
+        globals: init_table(),
+        init_string: std::ptr::null_mut(),
+        compiler_roots: Vec::new(),
+        gray_stack: Vec::new(),
+        bytes_allocated: 0,
+        next_gc: GC_INITIAL_THRESHOLD,
+        stress_gc: false,
+        log_gc: false,
+    };
+
+    vm.init_string = copy_string(&mut vm, "init");
+
+    vm.define_native("clock", 0, clock_native);
+
+    return vm;
+}
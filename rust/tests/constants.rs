@@ -0,0 +1,66 @@
+#![allow(clippy::needless_return)]
+
+use std::process::Command;
+
+fn run(name: &str, source: &str) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!("rlox-{}-{}.lox", name, std::process::id()));
+
+    std::fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    return output;
+}
+
+#[test]
+fn compiles_thousands_of_literals() {
+    let literals = (1..=2000)
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(" + ");
+
+    let output = run(
+        "literals",
+        &format!("print {};\nprint \"done\";\n", literals),
+    );
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2001000\ndone\n");
+}
+
+#[test]
+fn resolves_names_past_the_first_256_constants() {
+    let literals = (1..=300)
+        .map(|i| i.to_string())
+        .collect::<Vec<String>>()
+        .join(" + ");
+
+    let source = format!(
+        "print {};
+var total = 1;
+fun add(a, b) {{ return a + b; }}
+class Box {{
+  init(value) {{ this.value = value; }}
+  get() {{ return this.value; }}
+}}
+class Big < Box {{
+  get() {{ return super.get() * 2; }}
+}}
+var box = Big(total);
+box.value = add(box.value, 2);
+total = box.get();
+print total;
+",
+        literals
+    );
+
+    let output = run("names", &source);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "45150\n6\n");
+}
@@ -0,0 +1,75 @@
+#![allow(clippy::needless_return)]
+
+use lox::diagnostic::*;
+use lox::vm::*;
+
+fn compile(source: &str) -> Vec<Diagnostic> {
+    let mut vm = init_vm();
+
+    return lox::compiler::compile(source.to_string(), &mut vm).diagnostics;
+}
+
+#[test]
+fn reports_position_and_span() {
+    let diagnostics = compile("var a = 1;\nprint a +;\n");
+
+    assert_eq!(diagnostics.len(), 1);
+
+    let diagnostic = &diagnostics[0];
+
+    assert_eq!(diagnostic.severity, Severity::SeverityError);
+    assert_eq!(diagnostic.message, "Expect expression.");
+    assert_eq!((diagnostic.line, diagnostic.col), (2, 10));
+    assert_eq!((diagnostic.start, diagnostic.length), (20, 1));
+    assert_eq!(
+        diagnostic.to_string(),
+        "[line 2] Error at ';': Expect expression."
+    );
+}
+
+#[test]
+fn recovers_after_each_statement() {
+    let diagnostics = compile("print ;\nvar 1;\nprint \"ok\";\nreturn 1;\n");
+
+    let messages = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.to_string())
+        .collect::<Vec<String>>();
+
+    assert_eq!(
+        messages,
+        vec![
+            "[line 1] Error at ';': Expect expression.",
+            "[line 2] Error at '1': Expect variable name.",
+            "[line 4] Error at 'return': Can't return from top-level code.",
+        ]
+    );
+}
+
+#[test]
+fn reports_scanner_errors() {
+    let diagnostics = compile("print 1 @ 2;");
+
+    assert_eq!(
+        diagnostics[0].to_string(),
+        "[line 1] Error: Unexpected character."
+    );
+    assert_eq!((diagnostics[0].start, diagnostics[0].length), (8, 1));
+}
+
+#[test]
+fn valid_source_has_no_diagnostics() {
+    assert!(compile("fun f(a) { return a * 2; }\nprint f(21);\n").is_empty());
+}
+
+#[test]
+fn renders_carets_under_tab_indented_code() {
+    let source = "if (true) {\n\tprint 1 +;\n}\n";
+
+    let diagnostics = compile(source);
+
+    assert_eq!(
+        render_diagnostic(source, "tabs.lox", &diagnostics[0], false),
+        "error: Expect expression.\n --> tabs.lox:2:11\n  |\n2 |     print 1 +;\n  |              ^"
+    );
+}
@@ -0,0 +1,88 @@
+#![allow(clippy::needless_return)]
+
+use std::process::Command;
+
+const PROGRAM: &str = "fun counter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+
+class Node {
+  init(value, next) {
+    this.value = value;
+    this.next = next;
+  }
+  describe() { return \"node \" + this.label(); }
+  label() { return \"#\"; }
+}
+
+class Named < Node {
+  init(value, next, name) {
+    super.init(value, next);
+    this.name = name;
+  }
+  label() { return super.label() + this.name; }
+}
+
+var next = counter();
+var list = nil;
+var text = \"\";
+
+for (var i = 0; i < 200; i = i + 1) {
+  list = Named(next(), list, \"n\" + \"x\");
+  text = text + \"ab\";
+}
+
+var sum = 0;
+var node = list;
+
+while (node != nil) {
+  sum = sum + node.value;
+  node = node.next;
+}
+
+var wide = Node(0, nil);
+
+wide.a = 1; wide.b = 2; wide.c = 3; wide.d = 4; wide.e = 5;
+wide.f = 6; wide.g = 7; wide.h = 8; wide.i = 9; wide.j = 10;
+
+print sum;
+print list.describe();
+print text == text + \"\";
+print wide.a + wide.j;
+print next();
+";
+
+fn run(name: &str, flags: &[&str]) -> std::process::Output {
+    let path = std::env::temp_dir().join(format!("rlox-gc-{}-{}.lox", name, std::process::id()));
+
+    std::fs::write(&path, PROGRAM).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(flags)
+        .arg(&path)
+        .output()
+        .unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    return output;
+}
+
+#[test]
+fn stress_gc_matches_a_normal_run() {
+    let normal = run("normal", &[]);
+    let stressed = run("stress", &["--stress-gc"]);
+
+    assert!(normal.status.success());
+    assert!(stressed.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&normal.stdout),
+        "20100\nnode #nx\ntrue\n11\n201\n"
+    );
+    assert_eq!(stressed.stdout, normal.stdout);
+}
@@ -0,0 +1,83 @@
+#![allow(clippy::needless_return)]
+
+use lox::common::*;
+use lox::vm::*;
+
+fn run(source: &str) -> RuntimeError {
+    let mut vm = init_vm();
+
+    match vm.interpret(source.to_string(), false) {
+        InterpretResult::InterpretRuntimeError(error) => return error,
+        _ => panic!("Expected a runtime error."),
+    }
+}
+
+#[test]
+fn traces_nested_calls_down_to_script() {
+    let error = run(
+        "fun inner() {\n  return 1 - nil;\n}\n\nfun outer() {\n  return inner();\n}\n\nouter();\n",
+    );
+
+    assert_eq!(error.message, "Operands must be numbers.");
+    assert_eq!(
+        error.trace,
+        vec![
+            TraceFrame {
+                function: Some("inner".to_string()),
+                line: 2,
+            },
+            TraceFrame {
+                function: Some("outer".to_string()),
+                line: 6,
+            },
+            TraceFrame {
+                function: None,
+                line: 9,
+            },
+        ]
+    );
+    assert_eq!(
+        error.to_string(),
+        "Operands must be numbers.\n[line 2] in inner()\n[line 6] in outer()\n[line 9] in script"
+    );
+}
+
+#[test]
+fn traces_recursive_frames() {
+    let error =
+        run("fun fib(n) {\n  if (n < 2) return n + nil;\n  return fib(n - 1);\n}\nfib(3);\n");
+
+    let lines = error
+        .trace
+        .iter()
+        .map(|frame| frame.to_string())
+        .collect::<Vec<String>>();
+
+    assert_eq!(
+        lines,
+        vec![
+            "[line 2] in fib()",
+            "[line 3] in fib()",
+            "[line 3] in fib()",
+            "[line 5] in script",
+        ]
+    );
+}
+
+#[test]
+fn traces_top_level_errors_as_script() {
+    let error = run("print -\"x\";\n");
+
+    assert_eq!(
+        error.to_string(),
+        "Operand must be a number.\n[line 1] in script"
+    );
+}
+
+#[test]
+fn points_assignment_errors_at_the_variable() {
+    let error = run("var y;\ny = 1;\nx = 1;\n");
+
+    assert_eq!(error.message, "Undefined variable 'x'.");
+    assert_eq!((error.line, error.col, error.length), (3, 1, 1));
+}
@@ -0,0 +1,290 @@
+#![allow(clippy::needless_return)]
+
+use lox::bytecode::*;
+use lox::verify::*;
+use lox::vm::*;
+use std::process::Command;
+
+const OP_RETURN: u8 = 0;
+const OP_CONSTANT: u8 = 6;
+const OP_NIL: u8 = 7;
+const OP_PRINT: u8 = 14;
+const OP_POP: u8 = 15;
+const OP_GET_LOCAL: u8 = 19;
+const OP_JUMP: u8 = 21;
+
+const CONSTANT_NUMBER: u8 = 0;
+const CONSTANT_FUNCTION: u8 = 2;
+
+fn header() -> Vec<u8> {
+    let mut bytes = b"LOXC".to_vec();
+
+    bytes.extend_from_slice(&3u16.to_le_bytes());
+
+    return bytes;
+}
+
+/// Everything in a serialized function up to (not including) its constants:
+/// no name, one line run and one column record covering the whole chunk.
+fn function_prefix(arity: u32, code: &[u8], constant_count: u32) -> Vec<u8> {
+    let mut bytes = Vec::new();
+
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(&arity.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+
+    bytes.extend_from_slice(&(code.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(code);
+
+    bytes.extend_from_slice(&1u32.to_le_bytes());
+    bytes.extend_from_slice(&0u32.to_le_bytes());
+    bytes.extend_from_slice(&1i32.to_le_bytes());
+
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(&[0, 1, 1]);
+
+    bytes.extend_from_slice(&constant_count.to_le_bytes());
+
+    return bytes;
+}
+
+fn script(arity: u32, code: &[u8], numbers: &[f64]) -> Vec<u8> {
+    let mut bytes = header();
+
+    bytes.extend(function_prefix(arity, code, numbers.len() as u32));
+
+    for number in numbers.iter() {
+        bytes.push(CONSTANT_NUMBER);
+        bytes.extend_from_slice(&number.to_le_bytes());
+    }
+
+    return bytes;
+}
+
+fn nested_script(depth: usize) -> Vec<u8> {
+    let level = function_prefix(0, &[OP_NIL, OP_RETURN], 1);
+
+    let mut bytes = header();
+
+    for _ in 0..depth {
+        bytes.extend_from_slice(&level);
+        bytes.push(CONSTANT_FUNCTION);
+    }
+
+    bytes.extend(function_prefix(0, &[OP_NIL, OP_RETURN], 0));
+
+    return bytes;
+}
+
+fn rlox(args: &[&std::path::Path]) -> std::process::Output {
+    return Command::new(env!("CARGO_BIN_EXE_lox"))
+        .args(args)
+        .output()
+        .unwrap();
+}
+
+fn temp_path(name: &str, extension: &str) -> std::path::PathBuf {
+    return std::env::temp_dir().join(format!(
+        "rlox-verify-{}-{}.{}",
+        name,
+        std::process::id(),
+        extension
+    ));
+}
+
+fn run_bytecode(name: &str, bytes: &[u8]) -> std::process::Output {
+    let path = temp_path(name, "loxc");
+
+    std::fs::write(&path, bytes).unwrap();
+
+    let output = rlox(&[std::path::Path::new("run"), &path]);
+
+    std::fs::remove_file(&path).unwrap();
+
+    return output;
+}
+
+fn assert_rejected(output: std::process::Output, message: &str) {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(65), "stderr: {}", stderr);
+    assert!(stderr.contains(message), "stderr: {}", stderr);
+}
+
+#[test]
+fn runs_a_well_formed_chunk() {
+    let output = run_bytecode(
+        "valid",
+        &script(0, &[OP_CONSTANT, 0, OP_PRINT, OP_NIL, OP_RETURN], &[42.0]),
+    );
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "42\n");
+}
+
+#[test]
+fn rejects_unknown_opcodes() {
+    let output = run_bytecode("unknown", &script(0, &[200, OP_NIL, OP_RETURN], &[]));
+
+    assert_rejected(output, "offset 0000: Unknown opcode 200.");
+}
+
+#[test]
+fn reports_load_errors_by_kind() {
+    let mut vm = init_vm();
+
+    match vm.interpret_bytecode(&script(0, &[200, OP_NIL, OP_RETURN], &[])) {
+        Err(LoadError::LoadVerify(error)) => {
+            assert_eq!(error.offset, 0);
+            assert_eq!(error.kind, VerifyErrorKind::VerifyUnknownOpcode(200));
+        }
+        _ => panic!("expected a verification error"),
+    }
+
+    match vm.interpret_bytecode(b"LOX") {
+        Err(LoadError::LoadFormat(_)) => {}
+        _ => panic!("expected a format error"),
+    }
+}
+
+#[test]
+fn rejects_truncated_operands() {
+    let output = run_bytecode(
+        "truncated",
+        &script(0, &[OP_NIL, OP_RETURN, OP_CONSTANT], &[]),
+    );
+
+    assert_rejected(
+        output,
+        "offset 0002: Instruction operands run past the end of the chunk.",
+    );
+}
+
+#[test]
+fn rejects_jumps_into_operands() {
+    let output = run_bytecode(
+        "jump",
+        &script(
+            0,
+            &[OP_JUMP, 0, 1, OP_CONSTANT, 0, OP_POP, OP_NIL, OP_RETURN],
+            &[1.0],
+        ),
+    );
+
+    assert_rejected(output, "Jump target 4 is inside an instruction.");
+}
+
+#[test]
+fn rejects_stack_underflow() {
+    let output = run_bytecode(
+        "underflow",
+        &script(0, &[OP_POP, OP_POP, OP_NIL, OP_RETURN], &[]),
+    );
+
+    assert_rejected(output, "offset 0001: Stack underflow.");
+}
+
+#[test]
+fn rejects_locals_out_of_range() {
+    let output = run_bytecode("local", &script(0, &[OP_GET_LOCAL, 5, OP_RETURN], &[]));
+
+    assert_rejected(output, "Local slot 5 is out of range.");
+}
+
+#[test]
+fn rejects_scripts_with_parameters() {
+    let output = run_bytecode("arity", &script(1, &[OP_NIL, OP_RETURN], &[]));
+
+    assert_rejected(
+        output,
+        "Top-level script cannot take parameters or capture upvalues.",
+    );
+}
+
+#[test]
+fn rejects_deeply_nested_functions() {
+    let output = run_bytecode("nested", &nested_script(1000));
+
+    assert!(output.status.success());
+
+    let output = run_bytecode("deep", &nested_script(200_000));
+
+    assert_rejected(output, "Functions nested too deeply.");
+}
+
+#[test]
+fn reports_missing_files() {
+    let missing = temp_path("missing", "lox");
+    let out = temp_path("missing", "loxc");
+
+    for output in [
+        rlox(&[&missing]),
+        rlox(&[
+            std::path::Path::new("compile"),
+            &missing,
+            std::path::Path::new("-o"),
+            &out,
+        ]),
+        rlox(&[std::path::Path::new("run"), &out]),
+    ] {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        assert_eq!(output.status.code(), Some(74), "stderr: {}", stderr);
+        assert!(stderr.contains("Could not open file"), "stderr: {}", stderr);
+    }
+}
+
+#[test]
+fn round_trips_compiled_programs() {
+    let source = "fun counter() {
+  var count = 0;
+  fun increment() {
+    count = count + 1;
+    return count;
+  }
+  return increment;
+}
+
+class Greeter {
+  init(name) { this.name = name; }
+  greet() { return \"hello \" + this.name; }
+}
+
+class Loud < Greeter {
+  greet() { return super.greet() + \"!\"; }
+}
+
+var next = counter();
+next();
+print next();
+print Loud(\"lox\").greet();
+for (var i = 0; i < 3; i = i + 1) print i * 1.5;
+";
+
+    let source_path = temp_path("program", "lox");
+    let bytecode_path = temp_path("program", "loxc");
+
+    std::fs::write(&source_path, source).unwrap();
+
+    let compiled = rlox(&[
+        std::path::Path::new("compile"),
+        &source_path,
+        std::path::Path::new("-o"),
+        &bytecode_path,
+    ]);
+
+    assert!(compiled.status.success());
+
+    let interpreted = rlox(&[&source_path]);
+    let loaded = rlox(&[std::path::Path::new("run"), &bytecode_path]);
+
+    std::fs::remove_file(&source_path).unwrap();
+    std::fs::remove_file(&bytecode_path).unwrap();
+
+    assert!(loaded.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&loaded.stdout),
+        "2\nhello lox!\n0\n1.5\n3\n"
+    );
+    assert_eq!(loaded.stdout, interpreted.stdout);
+}
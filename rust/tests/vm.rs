@@ -0,0 +1,83 @@
+#![allow(clippy::needless_return)]
+
+use lox::common::*;
+use lox::vm::*;
+use lox::Value;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+fn is_ok(result: InterpretResult) -> bool {
+    return matches!(result, InterpretResult::InterpretOk);
+}
+
+fn double_native(_vm: &mut VirtualMachine, args: &[Value]) -> Result<Value, String> {
+    return match args[0] {
+        Value::ValNumber(number) => Ok(Value::ValNumber(number * 2.0)),
+        _ => Err("Argument must be a number.".to_string()),
+    };
+}
+
+#[test]
+fn calls_natives_defined_by_the_host() {
+    let mut vm = init_vm();
+
+    vm.define_native("double", 1, double_native);
+
+    assert!(is_ok(
+        vm.interpret("if (double(21) != 42) nil - 1;".to_string(), false)
+    ));
+
+    match vm.interpret("double(nil);".to_string(), false) {
+        InterpretResult::InterpretRuntimeError(error) => {
+            assert_eq!(error.message, "Argument must be a number.");
+        }
+        _ => panic!("expected a runtime error"),
+    }
+}
+
+#[test]
+fn keeps_escaped_closures_valid_after_a_runtime_error() {
+    let mut vm = init_vm();
+
+    let result = vm.interpret(
+        "var g;
+fun f() {
+  var a = 1;
+  var b = 2;
+  var c = 3;
+  fun h() { return c; }
+  g = h;
+  nil - 1;
+}
+f();"
+            .to_string(),
+        false,
+    );
+
+    assert!(matches!(result, InterpretResult::InterpretRuntimeError(_)));
+
+    assert!(is_ok(
+        vm.interpret("if (g() != 3) nil - 1;".to_string(), false)
+    ));
+}
+
+#[test]
+fn repl_keeps_state_between_lines() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lox"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"var x = 1;\nnil - 1;\nprint x;\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> > > 1\n> ");
+}